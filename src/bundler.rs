@@ -1,72 +1,669 @@
 use super::lexer;
-use std::collections::HashMap;
-use std::rc::Rc;
-use relative_path::{RelativePath, RelativePathBuf};
-use std::env::current_dir;
+use super::loader;
+use super::module_graph::ModuleGraph;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
 
 pub struct JavascriptBundle {
-    pub content: String,
+  pub content: String,
 }
 
-pub fn bundle(entry_module: String, module_map: HashMap<String, lexer::JavascriptModule>) -> JavascriptBundle {
+// The module registry every bundle runs on, which is also what the fixtures serve
+const RUNTIME: &str = include_str!("bloom.js");
+
+// Where a bundle gets the runtime from
+#[derive(Debug, Clone, PartialEq)]
+pub enum Runtime {
+  // imported from a URL, or from a path starting with ./, ../ or /, which the browser
+  // resolves against the bundle's own URL. A bare specifier would need an import map.
+  Import(String),
+  // written into the bundle, which then needs nothing else
+  Inline,
+}
+
+impl Default for Runtime {
+  fn default() -> Runtime {
+    Runtime::Import(String::from("/bloom.js"))
+  }
+}
+
+#[derive(Debug)]
+pub enum BundleError {
+  ModuleNotFound(String),
+  UnsupportedImportAttribute { file_path: String, specifier: String, key: String, value: String },
+}
+
+impl fmt::Display for BundleError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      BundleError::ModuleNotFound(file_path) => write!(f, "module {} was not found in the module map", file_path),
+      BundleError::UnsupportedImportAttribute { file_path, specifier, key, value } => {
+        write!(f, "{}: import attribute {}: '{}' on '{}' is not supported", file_path, key, value, specifier)
+      },
+    }
+  }
+}
+
+struct BundleContext<'a> {
+  module_graph: &'a ModuleGraph,
+  // modules in the order their insertModule calls must be written, dependencies first
+  order: Vec<String>,
+  visited: HashSet<String>,
+  // modules currently being walked, from the entry down to the current one
+  import_stack: Vec<String>,
+  // modules reached through a circular import, with every name they export
+  proxies: BTreeMap<String, BTreeSet<String>>,
+}
+
+pub fn bundle(module_graph: &ModuleGraph, runtime: &Runtime) -> Result<JavascriptBundle, BundleError> {
+  let mut context = BundleContext {
+    module_graph,
+    order: Vec::new(),
+    visited: HashSet::new(),
+    import_stack: Vec::new(),
+    proxies: BTreeMap::new(),
+  };
+
+  walk_module(&module_graph.entry, &mut context)?;
+
   let mut content = String::new();
-  content.push_str("import { insertModule, createModuleUrl, resolveImportSpecifier } from \"/bloom.js\";\n");
+  match runtime {
+    Runtime::Import(runtime_url) => {
+      let runtime_url = serde_json::to_string(runtime_url).unwrap();
+      content.push_str(format!("import {{ insertModule, insertProxyModule, createModuleUrl, resolveImportSpecifier }} from {};\n", runtime_url).as_str());
+    },
+    // the bundle is a module too, so the runtime's functions stay private to it
+    Runtime::Inline => {
+      for line in RUNTIME.lines() {
+        content.push_str(line.strip_prefix("export ").unwrap_or(line));
+        content.push('\n');
+      }
+    },
+  }
 
-  traverse_module(&entry_module, &mut content, Rc::new(module_map));
+  // Blob URL modules cannot import the runtime themselves, so rewritten import() calls
+  // reach the registry through a global. Bundles importing the runtime share its registry,
+  // and so the global, but an inlined runtime has a registry of its own, which another
+  // inlined bundle on the same page must not replace.
+  let resolve_global = match runtime {
+    Runtime::Import(_) => String::from("resolveImportSpecifier"),
+    Runtime::Inline => format!("resolveImportSpecifier_{:016x}", bundle_hash(module_graph, &context.order)?),
+  };
+  let has_dynamic_imports = context.order.iter().any(|file_path| {
+    find_module(module_graph, file_path).is_ok_and(|module| !module.dynamic_imports.is_empty())
+  });
+  if has_dynamic_imports {
+    content.push_str(format!("globalThis.{} = resolveImportSpecifier;\n", resolve_global).as_str());
+  }
+
+  // A module in a cycle is imported before its own blob URL can exist, so its
+  // importers are given a proxy module with the same exports instead
+  let mut proxy_urls: HashMap<&str, String> = HashMap::new();
+  for (index, (file_path, export_names)) in context.proxies.iter().enumerate() {
+    let proxy_url = format!("proxy{}", index);
+    let export_names: Vec<String> = export_names.iter().map(|name| format!("\"{}\"", name)).collect();
+    content.push_str(format!("insertProxyModule(\"{}\",[{}]);\n", file_path, export_names.join(",")).as_str());
+    content.push_str(format!("const {} = resolveImportSpecifier(\"{}\");\n", proxy_url, file_path).as_str());
+    proxy_urls.insert(file_path, proxy_url);
+  }
+
+  for file_path in context.order.iter() {
+    let module = find_module(module_graph, file_path)?;
+    emit_module(file_path, module, module_graph, &resolve_global, &mut content);
+
+    if let Some(proxy_url) = proxy_urls.get(file_path.as_str()) {
+      emit_proxy_link(file_path, &context.proxies[file_path], proxy_url, &mut content);
+    }
+  }
 
   content.push_str("import(resolveImportSpecifier(\"");
-  content.push_str(entry_module.as_str());
+  content.push_str(module_graph.entry.as_str());
   content.push_str("\"));");
 
-  println!("{}", content);
-
-  JavascriptBundle {
+  Ok(JavascriptBundle {
     content
+  })
+}
+
+// Identifies a bundle by its modules, so the same bundle always gets the same hash
+fn bundle_hash(module_graph: &ModuleGraph, order: &[String]) -> Result<u64, BundleError> {
+  let mut contents = Vec::new();
+  for file_path in order {
+    contents.extend_from_slice(file_path.as_bytes());
+    contents.extend_from_slice(find_module(module_graph, file_path)?.raw_source.as_bytes());
   }
+  Ok(loader::hash(&contents))
+}
+
+fn find_module<'a>(module_graph: &'a ModuleGraph, file_path: &str) -> Result<&'a lexer::JavascriptModule, BundleError> {
+  module_graph.modules.get(file_path).ok_or_else(|| BundleError::ModuleNotFound(String::from(file_path)))
 }
 
-fn traverse_module(file_path: &String, content: &mut String, module_map: Rc<HashMap<String, lexer::JavascriptModule>>) {
-  let full_path = RelativePath::new(file_path).to_path(current_dir().unwrap().as_path());
-  let module = module_map.get(full_path.to_str().unwrap()).expect(format!("File not found in module_map {}", full_path.to_str().unwrap()).as_str());
+fn walk_module(file_path: &str, context: &mut BundleContext) -> Result<(), BundleError> {
+  context.visited.insert(String::from(file_path));
+  context.import_stack.push(String::from(file_path));
 
-  let mut parent_path_buf = RelativePathBuf::from(file_path.as_str());
-  parent_path_buf.pop();
+  let module = find_module(context.module_graph, file_path)?;
 
-  for import in module.imports.iter() {
-    let mod_path = parent_path_buf.join_normalized(RelativePath::new(&import.specifier));
-    traverse_module(&mod_path.to_string(), content, Rc::clone(&module_map));
+  let dependencies = module.imports.iter().map(|import| (&import.specifier, &import.attributes, &import.module_id))
+    .chain(module.exports.iter().map(|export| (&export.specifier, &export.attributes, &export.module_id)));
+
+  for (specifier, attributes, module_id) in dependencies {
+    // externals are imported by the browser itself, attributes and all
+    let mod_path = match module_id {
+      Some(mod_path) => mod_path.clone(),
+      None => continue,
+    };
+    check_import_attributes(context.module_graph.file_path(file_path), specifier, attributes)?;
+
+    if context.import_stack.contains(&mod_path) {
+      if !context.proxies.contains_key(&mod_path) {
+        let mut export_names = BTreeSet::new();
+        collect_export_names(context.module_graph, &mod_path, &mut export_names, &mut HashSet::new())?;
+        // insertProxyModule declares a variable for each name, which a string name like
+        // `export { a as "a-b" }` or a reserved word like `export { a as if }` cannot be
+        export_names.retain(|name| name == "default" || (lexer::is_identifier(name) && !lexer::is_reserved_word(name)));
+        context.proxies.insert(mod_path, export_names);
+      }
+    } else if !context.visited.contains(&mod_path) {
+      walk_module(&mod_path, context)?;
+    }
+  }
+
+  for asset_reference in module.asset_references.iter() {
+    let asset_id = asset_reference.module_id.as_ref().ok_or_else(|| BundleError::ModuleNotFound(asset_reference.specifier.clone()))?;
+    if !context.module_graph.asset_urls.contains_key(asset_id) {
+      return Err(BundleError::ModuleNotFound(asset_id.clone()));
+    }
+  }
+
+  context.import_stack.pop();
+  context.order.push(String::from(file_path));
+
+  // import() resolves its module lazily, so a circular dynamic import never needs a proxy.
+  // The importer is already off the stack and in the order by the time its lazy modules
+  // are walked, so they never see it as a cycle.
+  for dynamic_import in module.dynamic_imports.iter() {
+    if let Some(mod_path) = &dynamic_import.module_id {
+      check_import_attributes(context.module_graph.file_path(file_path), &dynamic_import.specifier, &dynamic_import.attributes)?;
+      if !context.visited.contains(mod_path) {
+        walk_module(mod_path, context)?;
+      }
+    }
+  }
+
+  Ok(())
+}
+
+// Attributes are removed once they have chosen a loader, so any that no loader
+// understands are rejected rather than silently dropped
+fn check_import_attributes(file_path: &str, specifier: &str, attributes: &[lexer::ImportAttribute]) -> Result<(), BundleError> {
+  match attributes.iter().find(|attribute| attribute.key != "type" || (attribute.value != "json" && attribute.value != "css")) {
+    Some(attribute) => Err(BundleError::UnsupportedImportAttribute {
+      file_path: String::from(file_path),
+      specifier: String::from(specifier),
+      key: attribute.key.clone(),
+      value: attribute.value.clone(),
+    }),
+    None => Ok(()),
+  }
+}
+
+// Every name a module exports, following `export * from` into the modules it re-exports
+fn collect_export_names(module_graph: &ModuleGraph, file_path: &str, export_names: &mut BTreeSet<String>, visited: &mut HashSet<String>) -> Result<(), BundleError> {
+  if !visited.insert(String::from(file_path)) {
+    return Ok(());
+  }
+
+  let module = find_module(module_graph, file_path)?;
+  export_names.extend(module.exported_names());
+
+  for export in module.exports.iter().filter(|export| export.star_export) {
+    // the names an external re-exports are only known once the browser loads it
+    let mod_path = match &export.module_id {
+      Some(mod_path) => mod_path,
+      None => continue,
+    };
+    let mut star_names = BTreeSet::new();
+    collect_export_names(module_graph, mod_path, &mut star_names, visited)?;
+    // `export *` never re-exports a default export
+    star_names.remove("default");
+    export_names.extend(star_names);
   }
 
+  Ok(())
+}
+
+// resolve_global is the global that rewritten import() calls look modules up through
+fn emit_module(file_path: &str, module: &lexer::JavascriptModule, module_graph: &ModuleGraph, resolve_global: &str, content: &mut String) {
+  // (start, end, replacement) for every specifier in the module, apart from externals,
+  // which are left as they are unless an alias replaced them
+  let mut rewrites: Vec<(usize, usize, String)> = Vec::new();
+  let specifiers = module.imports.iter().map(|import| (&import.specifier, import.specifier_start, import.specifier_end, &import.module_id))
+    .chain(module.exports.iter().map(|export| (&export.specifier, export.specifier_start, export.specifier_end, &export.module_id)))
+    .chain(module.dynamic_imports.iter().map(|dynamic_import| (&dynamic_import.specifier, dynamic_import.specifier_start, dynamic_import.specifier_end, &dynamic_import.module_id)));
+  for (specifier, start, end, module_id) in specifiers {
+    if let (None, Some(aliased)) = (module_id, module_graph.aliased_externals.get(specifier)) {
+      rewrites.push((start, end + 1, escape_template_literal(aliased)));
+    }
+  }
+  for import in module.imports.iter() {
+    if let Some(mod_path) = &import.module_id {
+      let replacement = format!("${{resolveImportSpecifier(\"{}\")}}", mod_path);
+      rewrites.push((import.specifier_start, import.specifier_end + 1, replacement));
+      // every bundled module is JavaScript by the time it is imported, so the attributes
+      // that chose its loader would only make the browser reject it. They go along with
+      // the whitespace before them, from just after the closing quote.
+      if !import.attributes.is_empty() {
+        rewrites.push((import.specifier_end + 2, import.attributes_end + 1, String::new()));
+      }
+    }
+  }
   for export in module.exports.iter() {
-    let mod_path = parent_path_buf.join_normalized(RelativePath::new(&export.specifier));
-    traverse_module(&mod_path.to_string(), content, Rc::clone(&module_map));
+    if let Some(mod_path) = &export.module_id {
+      let replacement = format!("${{resolveImportSpecifier(\"{}\")}}", mod_path);
+      rewrites.push((export.specifier_start, export.specifier_end + 1, replacement));
+      if !export.attributes.is_empty() {
+        rewrites.push((export.specifier_end + 2, export.attributes_end + 1, String::new()));
+      }
+    }
+  }
+  for dynamic_import in module.dynamic_imports.iter() {
+    // The quotes are replaced as well, so the module is looked up when import() runs
+    // rather than when the bundle is loaded
+    if let Some(mod_path) = &dynamic_import.module_id {
+      let replacement = format!("{}(\"{}\")", resolve_global, mod_path);
+      rewrites.push((dynamic_import.specifier_start - 1, dynamic_import.specifier_end + 2, escape_template_literal(&replacement)));
+      if !dynamic_import.attributes.is_empty() {
+        rewrites.push((dynamic_import.options_start, dynamic_import.options_end + 1, String::new()));
+      }
+    }
   }
+  for asset_reference in module.asset_references.iter() {
+    // A blob: URL cannot be a base for relative URLs, so the asset is resolved against
+    // the bundle's own import.meta.url and the module is given an absolute URL.
+    // walk_module has already checked that the asset was emitted.
+    let asset_url = serde_json::to_string(&module_graph.asset_urls[asset_reference.module_id.as_ref().unwrap()]).unwrap();
+    let replacement = format!("${{JSON.stringify(new URL({}, import.meta.url).href)}}", asset_url);
+    rewrites.push((asset_reference.specifier_start - 1, asset_reference.specifier_end + 2, replacement));
+  }
+  rewrites.sort_by_key(|(start, _, _)| *start);
 
   content.push_str("insertModule(\"");
-  content.push_str(file_path.as_str());
+  content.push_str(file_path);
   content.push_str("\",createModuleUrl(`");
 
   let mut last_index: usize = 0;
-  for import in module.imports.iter() {
-    let mod_path = parent_path_buf.join_normalized(RelativePath::new(&import.specifier));
-    content.push_str(module.raw_source.get(last_index..import.specifier_start).unwrap());
-    content.push_str("${resolveImportSpecifier(\"");
-    content.push_str(&mod_path.to_string().as_str());
-    content.push_str("\")}");
-    last_index = import.specifier_end + 1;
+  for (start, end, replacement) in rewrites {
+    content.push_str(&escape_template_literal(&module.raw_source[last_index..start]));
+    content.push_str(&replacement);
+    last_index = end;
   }
+  content.push_str(&escape_template_literal(&module.raw_source[last_index..]));
+  content.push_str("`));\n");
+}
 
-  for export in module.exports.iter() {
-    let mod_path = parent_path_buf.join_normalized(RelativePath::new(&export.specifier));
-    content.push_str(module.raw_source.get(last_index..export.specifier_start).unwrap());
-    content.push_str("${resolveImportSpecifier(\"");
-    content.push_str(&mod_path.to_string().as_str());
-    content.push_str("\")}");
-    last_index = export.specifier_end + 1;
+// Escapes source text so that the cooked strings createModuleUrl receives from the
+// template literal are identical to the original source
+fn escape_template_literal(source: &str) -> String {
+  let mut escaped = String::with_capacity(source.len());
+  let mut chars = source.chars().peekable();
+  while let Some(c) = chars.next() {
+    match c {
+      '\\' => escaped.push_str("\\\\"),
+      '`' => escaped.push_str("\\`"),
+      '$' if chars.peek() == Some(&'{') => escaped.push_str("\\$"),
+      // template literals normalize line endings, which would otherwise drop carriage returns
+      '\r' => escaped.push_str("\\r"),
+      _ => escaped.push(c),
+    }
   }
+  escaped
+}
 
-  if last_index < module.raw_source.len() {
-    content.push_str(module.raw_source.get(last_index..module.raw_source.len()).unwrap());
+// Replaces the module just inserted at file_path with one that re-exports it and,
+// once it has been evaluated, passes its exports to the proxy's setters
+fn emit_proxy_link(file_path: &str, export_names: &BTreeSet<String>, proxy_url: &str, content: &mut String) {
+  let real_url = format!("${{resolveImportSpecifier(\"{}\")}}", file_path);
+
+  content.push_str("insertModule(\"");
+  content.push_str(file_path);
+  content.push_str("\",createModuleUrl(`");
+  content.push_str(format!("import * as real from '{}';import * as proxy from '${{{}}}';", real_url, proxy_url).as_str());
+  for export_name in export_names.iter() {
+    content.push_str(format!("proxy.set{}(real.{});", export_name, export_name).as_str());
+  }
+  content.push_str(format!("export * from '{}';", real_url).as_str());
+  if export_names.contains("default") {
+    content.push_str(format!("export {{ default }} from '{}';", real_url).as_str());
   }
   content.push_str("`));\n");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader;
+    use crate::module_graph::ModuleIds;
+    use crate::resolver;
+    use std::path::Path;
+
+    // The first module is the entry. Relative specifiers are resolved next to their
+    // importer, and any others are left external.
+    fn module_graph(modules: Vec<(&str, &str)>) -> ModuleGraph {
+        let mut module_graph = ModuleGraph::new(modules[0].0, Path::new(""), ModuleIds::Relative);
+        for (file_path, source) in modules {
+            insert_module(&mut module_graph, file_path, lexer::JavascriptLexer::new(String::from(source)).parse_module().unwrap());
+        }
+        module_graph
+    }
+
+    fn insert_module(module_graph: &mut ModuleGraph, file_path: &str, mut module: lexer::JavascriptModule) {
+        let resolve = |specifier: &str| Some(resolver::join(file_path, specifier)).filter(|_| !resolver::is_bare_specifier(specifier) && !resolver::is_url(specifier));
+        for import in module.imports.iter_mut() {
+            import.module_id = resolve(&import.specifier);
+        }
+        for export in module.exports.iter_mut() {
+            export.module_id = resolve(&export.specifier);
+        }
+        for dynamic_import in module.dynamic_imports.iter_mut() {
+            dynamic_import.module_id = resolve(&dynamic_import.specifier);
+        }
+        for asset_reference in module.asset_references.iter_mut() {
+            asset_reference.module_id = resolve(&asset_reference.specifier);
+        }
+        module_graph.insert(file_path, module).unwrap();
+    }
+
+    #[test]
+    fn names_global_after_bundle_when_runtime_is_inlined() {
+        let lazy_module_graph = |lazy_source| module_graph(vec![
+            ("src/main.js", "const route = () => import('./lazy.js');\n"),
+            ("src/lazy.js", lazy_source),
+        ]);
+        let bundle = bundle(&lazy_module_graph("export default 'lazy';\n"), &Runtime::Inline).unwrap();
+        assert!(!bundle.content.contains("globalThis.resolveImportSpecifier ="));
+        let resolve_global = bundle.content.split("\nglobalThis.").nth(1).unwrap().split(' ').next().unwrap();
+        assert!(resolve_global.starts_with("resolveImportSpecifier_"));
+        assert!(bundle.content.contains(&format!("const route = () => import({}(\"src/lazy.js\"));\n", resolve_global)));
+
+        // another bundle on the same page keeps a registry of its own
+        let other_bundle = super::bundle(&lazy_module_graph("export default 'other';\n"), &Runtime::Inline).unwrap();
+        assert!(!other_bundle.content.contains(resolve_global));
+    }
+
+    #[test]
+    fn imports_runtime_from_configured_url() {
+        let module_graph = module_graph(vec![("src/main.js", "export const a = 1;\n")]);
+        let bundle = bundle(&module_graph, &Runtime::Import(String::from("./vendor/bloom.js"))).unwrap();
+        assert!(bundle.content.starts_with("import { insertModule, insertProxyModule, createModuleUrl, resolveImportSpecifier } from \"./vendor/bloom.js\";\n"));
+    }
+
+    #[test]
+    fn inlines_runtime() {
+        let module_graph = module_graph(vec![("src/main.js", "export const a = 1;\n")]);
+        let bundle = bundle(&module_graph, &Runtime::Inline).unwrap();
+        assert!(bundle.content.starts_with("let registry = {};\nfunction insertModule(specifier, url) {\n"));
+        assert!(!bundle.content.contains("import {"));
+
+        // nothing the runtime declares is exported from the bundle
+        let runtime = &bundle.content[..bundle.content.find("insertModule(\"").unwrap()];
+        let runtime_module = lexer::JavascriptLexer::new(String::from(runtime)).parse_module().unwrap();
+        assert!(runtime_module.exported_names().is_empty());
+        assert!(runtime.lines().all(|line| !line.trim_start().starts_with("export ")));
+    }
+
+    #[test]
+    fn emits_shared_module_once() {
+        let module_graph = module_graph(vec![
+            ("src/main.js", "import { b } from './b.js';\nimport { c } from './c.js';\n"),
+            ("src/b.js", "import { d } from './d.js';\nexport const b = d;\n"),
+            ("src/c.js", "import { d } from './d.js';\nexport const c = d;\n"),
+            ("src/d.js", "export const d = 'd';\n"),
+        ]);
+        let bundle = bundle(&module_graph, &Runtime::default()).unwrap();
+        assert_eq!(bundle.content.matches("insertModule(\"src/d.js\"").count(), 1);
+        assert!(bundle.content.find("insertModule(\"src/d.js\"").unwrap() < bundle.content.find("insertModule(\"src/b.js\"").unwrap());
+        assert!(bundle.content.find("insertModule(\"src/d.js\"").unwrap() < bundle.content.find("insertModule(\"src/c.js\"").unwrap());
+        assert!(!bundle.content.contains("insertProxyModule(\""));
+    }
+
+    #[test]
+    fn emits_module_reached_through_different_paths_once() {
+        let module_graph = module_graph(vec![
+            ("src/main.js", "import { b } from './lib/b.js';\nimport { c } from './c.js';\n"),
+            ("src/lib/b.js", "import { c } from '../c.js';\nexport const b = c;\n"),
+            ("src/c.js", "import { main } from './main.js';\nexport const c = 'c';\n"),
+        ]);
+        let bundle = bundle(&module_graph, &Runtime::default()).unwrap();
+        assert_eq!(bundle.content.matches("insertModule(\"src/c.js\"").count(), 1);
+        assert!(bundle.content.contains("import { c } from '${resolveImportSpecifier(\"src/c.js\")}';\nexport const b = c;\n"));
+        // the entry is reached again from c.js and proxied, rather than emitted a second time
+        assert!(bundle.content.contains("insertProxyModule(\"src/main.js\""));
+    }
+
+    #[test]
+    fn rewrites_side_effect_import() {
+        let module_graph = module_graph(vec![
+            ("src/main.js", "import './polyfill.js';\nimport { b } from './b.js';\n"),
+            ("src/polyfill.js", "window.polyfilled = true;\n"),
+            ("src/b.js", "export const b = 'b';\n"),
+        ]);
+        let bundle = bundle(&module_graph, &Runtime::default()).unwrap();
+        assert!(bundle.content.contains("insertModule(\"src/polyfill.js\""));
+        assert!(bundle.content.contains("insertModule(\"src/main.js\",createModuleUrl(`import '${resolveImportSpecifier(\"src/polyfill.js\")}';\nimport { b } from '${resolveImportSpecifier(\"src/b.js\")}';\n`));\n"));
+    }
+
+    #[test]
+    fn rewrites_namespace_import() {
+        let module_graph = module_graph(vec![
+            ("src/main.js", "import * as utils from './utils.js';\nutils.run();\n"),
+            ("src/utils.js", "export function run() {}\n"),
+        ]);
+        let bundle = bundle(&module_graph, &Runtime::default()).unwrap();
+        assert!(bundle.content.contains("insertModule(\"src/utils.js\""));
+        assert!(bundle.content.contains("insertModule(\"src/main.js\",createModuleUrl(`import * as utils from '${resolveImportSpecifier(\"src/utils.js\")}';\nutils.run();\n`));\n"));
+    }
+
+    #[test]
+    fn rewrites_dynamic_import() {
+        let module_graph = module_graph(vec![
+            ("src/main.js", "export { b } from './b.js';\nconst route = () => import('./lazy.js');\nimport { c } from './c.js';\n"),
+            ("src/lazy.js", "import { c } from './c.js';\nexport default () => import(\"./main.js\");\n"),
+            ("src/b.js", "export const b = 'b';\n"),
+            ("src/c.js", "export const c = 'c';\n"),
+        ]);
+        let bundle = bundle(&module_graph, &Runtime::default()).unwrap();
+        assert!(bundle.content.contains("\nglobalThis.resolveImportSpecifier = resolveImportSpecifier;\n"));
+        assert!(bundle.content.contains("insertModule(\"src/main.js\",createModuleUrl(`export { b } from '${resolveImportSpecifier(\"src/b.js\")}';\nconst route = () => import(resolveImportSpecifier(\"src/lazy.js\"));\nimport { c } from '${resolveImportSpecifier(\"src/c.js\")}';\n`));\n"));
+        assert!(bundle.content.contains("export default () => import(resolveImportSpecifier(\"src/main.js\"));\n`));\n"));
+        assert_eq!(bundle.content.matches("insertModule(\"src/lazy.js\"").count(), 1);
+        assert!(!bundle.content.contains("insertProxyModule(\""));
+    }
+
+    #[test]
+    fn does_not_proxy_importer_of_lazy_module() {
+        let module_graph = module_graph(vec![
+            ("src/main.js", "export const name = 'main';\nconst route = () => import('./lazy.js');\n"),
+            ("src/lazy.js", "import { name } from './main.js';\nexport default name;\n"),
+        ]);
+        let bundle = bundle(&module_graph, &Runtime::default()).unwrap();
+        assert!(!bundle.content.contains("insertProxyModule(\""));
+        assert_eq!(bundle.content.matches("insertModule(\"src/main.js\"").count(), 1);
+        assert!(bundle.content.find("insertModule(\"src/main.js\"").unwrap() < bundle.content.find("insertModule(\"src/lazy.js\"").unwrap());
+    }
+
+    #[test]
+    fn rewrites_references_in_exported_initializers() {
+        let mut module_graph = module_graph(vec![
+            ("src/main.js", "export const route = () => import('./lazy.js');\nexport const logo = new URL('./logo.png', import.meta.url);\n"),
+            ("src/lazy.js", "export default 'lazy';\n"),
+        ]);
+        module_graph.asset_urls.insert(String::from("src/logo.png"), String::from("assets/logo-1234abcd.png"));
+        let bundle = bundle(&module_graph, &Runtime::default()).unwrap();
+        assert!(bundle.content.contains("export const route = () => import(resolveImportSpecifier(\"src/lazy.js\"));\n"));
+        assert!(bundle.content.contains("export const logo = new URL(${JSON.stringify(new URL(\"assets/logo-1234abcd.png\", import.meta.url).href)}, import.meta.url);\n"));
+        assert_eq!(bundle.content.matches("insertModule(\"src/lazy.js\"").count(), 1);
+    }
+
+    #[test]
+    fn escapes_template_literal_characters() {
+        assert_eq!(escape_template_literal("const s = `a ${b} c`;"), "const s = \\`a \\${b} c\\`;");
+        assert_eq!(escape_template_literal("const s = '\\n\\'';"), "const s = '\\\\n\\\\'';");
+        assert_eq!(escape_template_literal("const price = '$5' + '{';"), "const price = '$5' + '{';");
+        assert_eq!(escape_template_literal("a;\r\nb;"), "a;\\r\nb;");
+    }
+
+    #[test]
+    fn escapes_module_source_around_import_holes() {
+        let module_graph = module_graph(vec![
+            ("src/main.js", "import { b } from './b.js';\nconst greeting = `hi ${b}`;\nconst re = /\\d+`/g;\nconst s = \"\\\"\\t\";\n"),
+            ("src/b.js", "export { b } from './c.js';\nconsole.log(String.raw`\\u{61}`);\n"),
+            ("src/c.js", "const b = 'b';\nexport { b };\n"),
+        ]);
+        let bundle = bundle(&module_graph, &Runtime::default()).unwrap();
+        assert!(bundle.content.contains("insertModule(\"src/main.js\",createModuleUrl(`import { b } from '${resolveImportSpecifier(\"src/b.js\")}';\nconst greeting = \\`hi \\${b}\\`;\nconst re = /\\\\d+\\`/g;\nconst s = \"\\\\\"\\\\t\";\n`));\n"));
+        assert!(bundle.content.contains("insertModule(\"src/b.js\",createModuleUrl(`export { b } from '${resolveImportSpecifier(\"src/c.js\")}';\nconsole.log(String.raw\\`\\\\u{61}\\`);\n`));\n"));
+    }
+
+    #[test]
+    fn proxies_circular_import() {
+        let module_graph = module_graph(vec![
+            ("src/main.js", "import { a } from './a.js';\n"),
+            ("src/a.js", "import b from './b.js';\nconst a = 'a';\nexport { a };\n"),
+            ("src/b.js", "import { a } from './a.js';\nexport default () => a;\n"),
+        ]);
+        let bundle = bundle(&module_graph, &Runtime::default()).unwrap();
+        assert!(bundle.content.contains("insertProxyModule(\"src/a.js\",[\"a\"]);\nconst proxy0 = resolveImportSpecifier(\"src/a.js\");\n"));
+        assert_eq!(bundle.content.matches("insertModule(\"src/b.js\"").count(), 1);
+        // a.js is inserted once for real and once more for the module linking it to its proxy
+        assert_eq!(bundle.content.matches("insertModule(\"src/a.js\"").count(), 2);
+        assert!(bundle.content.contains("import * as proxy from '${proxy0}';proxy.seta(real.a);export * from '${resolveImportSpecifier(\"src/a.js\")}';`));\n"));
+        assert!(bundle.content.find("insertProxyModule(").unwrap() < bundle.content.find("insertModule(\"src/b.js\"").unwrap());
+        assert!(bundle.content.find("insertModule(\"src/b.js\"").unwrap() < bundle.content.find("insertModule(\"src/a.js\"").unwrap());
+    }
+
+    #[test]
+    fn proxies_every_export_of_circular_import() {
+        let module_graph = module_graph(vec![
+            ("src/main.js", "import { a } from './a.js';
+"),
+            ("src/a.js", "import { b } from './b.js';
+export const a = 'a', unused = 1;
+export default function () {}
+export * from './c.js';
+export { c as \"c-c\" } from './c.js';
+export { c as if, c as yield } from './c.js';
+"),
+            ("src/b.js", "import { a } from './a.js';
+export const b = () => a;
+"),
+            ("src/c.js", "export const c = 'c';
+export default 'c';
+"),
+        ]);
+        let bundle = bundle(&module_graph, &Runtime::default()).unwrap();
+        assert!(bundle.content.contains("insertProxyModule(\"src/a.js\",[\"a\",\"c\",\"default\",\"unused\"]);\n"));
+        assert!(bundle.content.contains("proxy.seta(real.a);proxy.setc(real.c);proxy.setdefault(real.default);proxy.setunused(real.unused);"));
+        assert!(bundle.content.contains("export { default } from '${resolveImportSpecifier(\"src/a.js\")}';`));\n"));
+    }
+
+    #[test]
+    fn rewrites_bare_specifiers_to_resolved_files() {
+        let mut module_graph = module_graph(vec![
+            ("src/main.js", "import { html } from 'lit';\nexport * from './b.js';\n"),
+            ("src/b.js", "export const b = 'b';\n"),
+            ("node_modules/lit/index.js", "export const html = () => {};\n"),
+        ]);
+        module_graph.modules.get_mut("src/main.js").unwrap().imports[0].module_id = Some(String::from("node_modules/lit/index.js"));
+        let bundle = bundle(&module_graph, &Runtime::default()).unwrap();
+        assert!(bundle.content.contains("insertModule(\"node_modules/lit/index.js\""));
+        assert!(bundle.content.contains("import { html } from '${resolveImportSpecifier(\"node_modules/lit/index.js\")}';\nexport * from '${resolveImportSpecifier(\"src/b.js\")}';\n"));
+    }
+
+    #[test]
+    fn leaves_external_specifiers_untouched() {
+        let module_graph = module_graph(vec![
+            ("src/main.js", "import { html } from 'lit';\nimport data from 'https://cdn.example.com/data.json' with { type: 'json' };\nexport * from 'lit/directives.js';\nconst chart = () => import('chart');\n"),
+        ]);
+        let bundle = bundle(&module_graph, &Runtime::default()).unwrap();
+        assert!(bundle.content.contains("insertModule(\"src/main.js\",createModuleUrl(`import { html } from 'lit';\nimport data from 'https://cdn.example.com/data.json' with { type: 'json' };\nexport * from 'lit/directives.js';\nconst chart = () => import('chart');\n`));\n"));
+        assert_eq!(bundle.content.matches("insertModule(").count(), 1);
+    }
+
+    #[test]
+    fn rewrites_externals_replaced_by_aliases() {
+        let mut module_graph = module_graph(vec![
+            ("src/main.js", "import React from 'react';\nexport * from 'react';\nconst dom = () => import(\"react-dom\");\nimport { html } from 'lit';\n"),
+        ]);
+        module_graph.aliased_externals.insert(String::from("react"), String::from("https://esm.sh/react@18"));
+        module_graph.aliased_externals.insert(String::from("react-dom"), String::from("https://esm.sh/react-dom@18"));
+        let bundle = bundle(&module_graph, &Runtime::default()).unwrap();
+        assert!(bundle.content.contains("createModuleUrl(`import React from 'https://esm.sh/react@18';\nexport * from 'https://esm.sh/react@18';\nconst dom = () => import(\"https://esm.sh/react-dom@18\");\nimport { html } from 'lit';\n`)"));
+    }
+
+    #[test]
+    fn rejects_unsupported_import_attributes() {
+        let module_graph = module_graph(vec![
+            ("src/main.js", "import styles from './styles.txt' with { type: 'text' };\n"),
+            ("src/styles.txt", "body {}\n"),
+        ]);
+        let err = bundle(&module_graph, &Runtime::default()).err().unwrap();
+        assert_eq!(err.to_string(), "src/main.js: import attribute type: 'text' on './styles.txt' is not supported");
+    }
+
+    #[test]
+    fn reports_file_paths_rather_than_hashed_ids() {
+        let mut module_graph = ModuleGraph::new("src/main.js", Path::new(""), ModuleIds::Hashed);
+        let mut module = lexer::JavascriptLexer::new(String::from("import styles from './styles.txt' with { type: 'text' };\n")).parse_module().unwrap();
+        module.imports[0].module_id = Some(module_graph.module_id("src/styles.txt"));
+        module_graph.insert("src/main.js", module).unwrap();
+        module_graph.insert("src/styles.txt", lexer::JavascriptLexer::new(String::from("")).parse_module().unwrap()).unwrap();
+        let err = bundle(&module_graph, &Runtime::default()).err().unwrap();
+        assert_eq!(err.to_string(), "src/main.js: import attribute type: 'text' on './styles.txt' is not supported");
+    }
+
+    #[test]
+    fn strips_import_attributes_from_json_imports() {
+        let mut module_graph = module_graph(vec![
+            ("src/main.js", "import config from './config.json' with { type: 'json' };\nexport { default as settings } from './config.json' with { type: 'json' };\n"),
+        ]);
+        insert_module(&mut module_graph, "src/config.json", loader::load_json(String::from("{ \"a\": 1 }")).unwrap());
+        let bundle = bundle(&module_graph, &Runtime::default()).unwrap();
+        assert!(bundle.content.contains("insertModule(\"src/config.json\",createModuleUrl(`export default JSON.parse(\"{ \\\\\"a\\\\\": 1 }\");\n`));\n"));
+        assert!(bundle.content.contains("import config from '${resolveImportSpecifier(\"src/config.json\")}';\nexport { default as settings } from '${resolveImportSpecifier(\"src/config.json\")}';\n"));
+    }
+
+    #[test]
+    fn strips_import_attributes_from_dynamic_imports() {
+        let mut module_graph = module_graph(vec![
+            ("src/main.js", "const config = () => import('./config.json', { with: { type: 'json' } });\nconst lit = () => import('lit', { with: { type: 'javascript' } });\n"),
+        ]);
+        insert_module(&mut module_graph, "src/config.json", loader::load_json(String::from("{}")).unwrap());
+        let bundle = bundle(&module_graph, &Runtime::default()).unwrap();
+        assert!(bundle.content.contains("const config = () => import(resolveImportSpecifier(\"src/config.json\"));\nconst lit = () => import('lit', { with: { type: 'javascript' } });\n"));
+    }
+
+    #[test]
+    fn rejects_unsupported_attributes_on_dynamic_imports() {
+        let mut module_graph = module_graph(vec![
+            ("src/main.js", "const config = () => import('./config.json', { with: { type: 'yaml' } });\n"),
+        ]);
+        insert_module(&mut module_graph, "src/config.json", loader::load_json(String::from("{}")).unwrap());
+        let err = bundle(&module_graph, &Runtime::default()).err().unwrap();
+        assert_eq!(err.to_string(), "src/main.js: import attribute type: 'yaml' on './config.json' is not supported");
+    }
+
+    #[test]
+    fn rewrites_asset_references_to_emitted_files() {
+        let mut module_graph = module_graph(vec![
+            ("src/main.js", "import logo from './logo.png';\nconst icon = new URL('./icons/icon.svg', import.meta.url);\n"),
+        ]);
+        insert_module(&mut module_graph, "src/logo.png", loader::load_asset("src/logo.png"));
+        module_graph.asset_urls.insert(String::from("src/logo.png"), String::from("assets/logo-1234abcd.png"));
+        module_graph.asset_urls.insert(String::from("src/icons/icon.svg"), String::from("assets/icon-5678abcd.svg"));
+        let bundle = bundle(&module_graph, &Runtime::default()).unwrap();
+        assert!(bundle.content.contains("insertModule(\"src/logo.png\",createModuleUrl(`export default new URL(${JSON.stringify(new URL(\"assets/logo-1234abcd.png\", import.meta.url).href)}, import.meta.url).href;\n`));\n"));
+        assert!(bundle.content.contains("const icon = new URL(${JSON.stringify(new URL(\"assets/icon-5678abcd.svg\", import.meta.url).href)}, import.meta.url);\n"));
+    }
+}
@@ -0,0 +1,257 @@
+use super::resolver::{self, AliasTarget, TsconfigPaths};
+use relative_path::{RelativePath, RelativePathBuf};
+use std::fmt;
+
+// The build settings in rawsemble.json
+#[derive(Default)]
+pub struct Config {
+  // `"~/": "./src/"` or `"react": "preact/compat"`
+  pub aliases: Vec<(String, AliasTarget)>,
+  // the tsconfig.json whose `paths` are used as aliases too, relative to the working directory
+  pub tsconfig: Option<String>,
+  // the directory module ids are relative to, relative to the working directory
+  pub root: Option<String>,
+  // names modules by a hash of their id instead
+  pub hashed_ids: bool,
+  // the URL the bundle imports the runtime from
+  pub runtime: Option<String>,
+  // writes the runtime into the bundle instead
+  pub inline_runtime: bool,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+  InvalidJson(serde_json::Error),
+  NotAnObject(String),
+  NotAString(String),
+  NotABoolean(String),
+  NotAnArrayOfStrings(String),
+  NotAUrl { field: String, value: String },
+}
+
+impl fmt::Display for ConfigError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ConfigError::InvalidJson(err) => write!(f, "invalid JSON: {}", err),
+      ConfigError::NotAnObject(field) => write!(f, "\"{}\" must be an object", field),
+      ConfigError::NotAString(field) => write!(f, "\"{}\" must be a string", field),
+      ConfigError::NotABoolean(field) => write!(f, "\"{}\" must be true or false", field),
+      ConfigError::NotAnArrayOfStrings(field) => write!(f, "\"{}\" must be an array of strings", field),
+      ConfigError::NotAUrl { field, value } => {
+        write!(f, "\"{}\" must be a URL or a path starting with ./, ../ or /, not '{}'", field, value)
+      },
+    }
+  }
+}
+
+impl Config {
+  // Paths in the config are relative to the directory of the config file
+  pub fn parse(source: &str, config_path: &str) -> Result<Config, ConfigError> {
+    let mut base_path_buf = RelativePathBuf::from(config_path);
+    base_path_buf.pop();
+
+    let json: serde_json::Value = serde_json::from_str(source).map_err(ConfigError::InvalidJson)?;
+    let json = json.as_object().ok_or_else(|| ConfigError::NotAnObject(String::from("config")))?;
+
+    let mut aliases = Vec::new();
+    if let Some(alias) = json.get("alias") {
+      let alias = alias.as_object().ok_or_else(|| ConfigError::NotAnObject(String::from("alias")))?;
+      for (key, target) in alias.iter() {
+        let target = target.as_str().ok_or_else(|| ConfigError::NotAString(key.clone()))?;
+        let target = if resolver::is_bare_specifier(target) || resolver::is_url(target) {
+          AliasTarget::Specifier(String::from(target))
+        } else {
+          AliasTarget::Path(base_path_buf.join_normalized(RelativePath::new(target.trim_start_matches('/'))).to_string())
+        };
+        aliases.push((String::from(key.trim_end_matches('/')), target));
+      }
+    }
+
+    let tsconfig = match json.get("tsconfig") {
+      Some(tsconfig) => {
+        let tsconfig = tsconfig.as_str().ok_or_else(|| ConfigError::NotAString(String::from("tsconfig")))?;
+        Some(base_path_buf.join_normalized(RelativePath::new(tsconfig)).to_string())
+      },
+      None => None,
+    };
+
+    let root = match json.get("root") {
+      Some(root) => {
+        let root = root.as_str().ok_or_else(|| ConfigError::NotAString(String::from("root")))?;
+        Some(base_path_buf.join_normalized(RelativePath::new(root)).to_string())
+      },
+      None => None,
+    };
+
+    let hashed_ids = match json.get("hashedIds") {
+      Some(hashed_ids) => hashed_ids.as_bool().ok_or_else(|| ConfigError::NotABoolean(String::from("hashedIds")))?,
+      None => false,
+    };
+
+    // the runtime is a URL the page serves, so it is left as it is
+    let runtime = match json.get("runtime") {
+      Some(runtime) => {
+        let runtime = runtime.as_str().ok_or_else(|| ConfigError::NotAString(String::from("runtime")))?;
+        if resolver::is_bare_specifier(runtime) {
+          return Err(ConfigError::NotAUrl { field: String::from("runtime"), value: String::from(runtime) });
+        }
+        Some(String::from(runtime))
+      },
+      None => None,
+    };
+
+    let inline_runtime = match json.get("inlineRuntime") {
+      Some(inline_runtime) => inline_runtime.as_bool().ok_or_else(|| ConfigError::NotABoolean(String::from("inlineRuntime")))?,
+      None => false,
+    };
+
+    Ok(Config { aliases, tsconfig, root, hashed_ids, runtime, inline_runtime })
+  }
+}
+
+// Reads `compilerOptions.baseUrl` and `compilerOptions.paths` from a tsconfig.json, which
+// may have comments and trailing commas
+pub fn parse_tsconfig_paths(source: &str, tsconfig_path: &str) -> Result<TsconfigPaths, ConfigError> {
+  let mut tsconfig_directory = RelativePathBuf::from(tsconfig_path);
+  tsconfig_directory.pop();
+
+  let json: serde_json::Value = serde_json::from_str(&strip_jsonc(source)).map_err(ConfigError::InvalidJson)?;
+  let compiler_options = match json.get("compilerOptions") {
+    Some(compiler_options) => compiler_options.as_object().ok_or_else(|| ConfigError::NotAnObject(String::from("compilerOptions")))?,
+    None => return Ok(TsconfigPaths::default()),
+  };
+
+  let base_url = match compiler_options.get("baseUrl") {
+    Some(base_url) => {
+      let base_url = base_url.as_str().ok_or_else(|| ConfigError::NotAString(String::from("baseUrl")))?;
+      Some(tsconfig_directory.join_normalized(RelativePath::new(base_url)).to_string())
+    },
+    None => None,
+  };
+
+  // without a baseUrl, paths are relative to the tsconfig.json itself
+  let paths_base = RelativePathBuf::from(base_url.clone().unwrap_or_else(|| tsconfig_directory.to_string()));
+  let mut paths = Vec::new();
+  if let Some(path_patterns) = compiler_options.get("paths") {
+    let path_patterns = path_patterns.as_object().ok_or_else(|| ConfigError::NotAnObject(String::from("paths")))?;
+    for (pattern, targets) in path_patterns.iter() {
+      let targets = targets.as_array()
+        .and_then(|targets| targets.iter().map(|target| target.as_str()).collect::<Option<Vec<&str>>>())
+        .ok_or_else(|| ConfigError::NotAnArrayOfStrings(pattern.clone()))?;
+      let targets = targets.iter().map(|target| paths_base.join_normalized(RelativePath::new(target)).to_string()).collect();
+      paths.push((pattern.clone(), targets));
+    }
+  }
+
+  Ok(TsconfigPaths { base_url, paths })
+}
+
+// Removes the comments and trailing commas JSON does not allow, leaving strings as they are
+fn strip_jsonc(source: &str) -> String {
+  let mut json = String::with_capacity(source.len());
+  // where the last comma was pushed, until something other than whitespace or a comment follows it
+  let mut last_comma: Option<usize> = None;
+  let mut chars = source.chars().peekable();
+  while let Some(c) = chars.next() {
+    match c {
+      '/' if chars.peek() == Some(&'/') => {
+        while chars.peek().is_some_and(|c| *c != '\n') {
+          chars.next();
+        }
+      },
+      '/' if chars.peek() == Some(&'*') => {
+        chars.next();
+        let mut last = ' ';
+        for c in chars.by_ref() {
+          if last == '*' && c == '/' {
+            break;
+          }
+          last = c;
+        }
+      },
+      c if c.is_whitespace() => json.push(c),
+      ',' => {
+        last_comma = Some(json.len());
+        json.push(c);
+      },
+      '}' | ']' => {
+        if let Some(comma_index) = last_comma.take() {
+          json.remove(comma_index);
+        }
+        json.push(c);
+      },
+      '"' => {
+        last_comma = None;
+        json.push(c);
+        while let Some(c) = chars.next() {
+          json.push(c);
+          match c {
+            '\\' => json.extend(chars.next()),
+            '"' => break,
+            _ => (),
+          }
+        }
+      },
+      _ => {
+        last_comma = None;
+        json.push(c);
+      },
+    }
+  }
+  json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_aliases_relative_to_config() {
+        let config = Config::parse(r#"{ "alias": { "~/": "./src/", "@app/ui": "../packages/ui/src", "react": "preact/compat" }, "tsconfig": "tsconfig.json" }"#, "web/rawsemble.json").unwrap();
+        assert_eq!(config.aliases, vec![
+            (String::from("~"), AliasTarget::Path(String::from("web/src"))),
+            (String::from("@app/ui"), AliasTarget::Path(String::from("packages/ui/src"))),
+            (String::from("react"), AliasTarget::Specifier(String::from("preact/compat"))),
+        ]);
+        assert_eq!(config.tsconfig, Some(String::from("web/tsconfig.json")));
+        assert_eq!(config.root, None);
+        assert!(!config.hashed_ids);
+
+        let err = Config::parse(r#"{ "alias": { "~": 1 } }"#, "rawsemble.json").err().unwrap();
+        assert_eq!(err.to_string(), "\"~\" must be a string");
+    }
+
+    #[test]
+    fn parses_output_settings() {
+        let config = Config::parse(r#"{ "root": "..", "hashedIds": true, "runtime": "./vendor/bloom.js", "inlineRuntime": false }"#, "web/app/rawsemble.json").unwrap();
+        assert_eq!(config.root, Some(String::from("web")));
+        assert!(config.hashed_ids);
+        assert_eq!(config.runtime, Some(String::from("./vendor/bloom.js")));
+        assert!(!config.inline_runtime);
+
+        let err = Config::parse(r#"{ "hashedIds": "yes" }"#, "rawsemble.json").err().unwrap();
+        assert_eq!(err.to_string(), "\"hashedIds\" must be true or false");
+        let err = Config::parse(r#"{ "runtime": "vendor/bloom.js" }"#, "rawsemble.json").err().unwrap();
+        assert_eq!(err.to_string(), "\"runtime\" must be a URL or a path starting with ./, ../ or /, not 'vendor/bloom.js'");
+    }
+
+    #[test]
+    fn parses_tsconfig_paths_with_comments() {
+        let tsconfig = r#"{
+            // compiled by tsc, bundled by rawsemble
+            "compilerOptions": {
+                "baseUrl": "./src", /* "baseUrl": "." */
+                "paths": {
+                    "@app/*": ["app/*", "../shared/*",],
+                    "http://*": ["vendor/*"],
+                },
+            },
+        }"#;
+        let tsconfig_paths = parse_tsconfig_paths(tsconfig, "web/tsconfig.json").unwrap();
+        assert_eq!(tsconfig_paths.base_url, Some(String::from("web/src")));
+        assert_eq!(tsconfig_paths.paths, vec![
+            (String::from("@app/*"), vec![String::from("web/src/app/*"), String::from("web/shared/*")]),
+            (String::from("http://*"), vec![String::from("web/src/vendor/*")]),
+        ]);
+    }
+}
@@ -0,0 +1,224 @@
+use super::resolver;
+use relative_path::{RelativePath, RelativePathBuf};
+use std::fmt;
+
+// An import map, read from the JSON the page is deployed with, so that the bundle resolves
+// specifiers the same way the browser will
+pub struct ImportMap {
+  imports: Vec<Mapping>,
+  // the mappings of each scope, with the scope's own address and whether it is a prefix
+  scopes: Vec<(Address, bool, Vec<Mapping>)>,
+  // the directory of the map file, which `/` in the map and in specifiers stands for
+  base_path_buf: RelativePathBuf,
+}
+
+// Where an import map sends a specifier
+#[derive(Debug, PartialEq)]
+pub enum ImportMapTarget {
+  // a file, relative to the working directory
+  Path(String),
+  Url(String),
+}
+
+#[derive(Debug)]
+pub enum ImportMapError {
+  InvalidJson(serde_json::Error),
+  NotAnObject(String),
+  InvalidTarget { key: String, target: String },
+  PrefixWithoutSlash { key: String, target: String },
+}
+
+impl fmt::Display for ImportMapError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ImportMapError::InvalidJson(err) => write!(f, "invalid JSON: {}", err),
+      ImportMapError::NotAnObject(field) => write!(f, "\"{}\" must be an object", field),
+      ImportMapError::InvalidTarget { key, target } => {
+        write!(f, "'{}' must map to a URL or to a path starting with ./, ../ or /, not '{}'", key, target)
+      },
+      ImportMapError::PrefixWithoutSlash { key, target } => write!(f, "'{}' ends with / but '{}' does not", key, target),
+    }
+  }
+}
+
+// Keys and targets once relative paths have been resolved, so that `./lit.js` in the map
+// matches `../lit.js` in a module one directory down
+#[derive(Debug, PartialEq)]
+enum Address {
+  // relative to the working directory
+  Path(String),
+  Url(String),
+  Bare(String),
+}
+
+struct Mapping {
+  key: Address,
+  // `lit/` maps every specifier that starts with it
+  prefix: bool,
+  target: Address,
+}
+
+impl ImportMap {
+  // Paths in the map, whether they start with ./ or /, are relative to the directory of the
+  // map file, which is taken to be the directory the page is served from
+  pub fn parse(source: &str, map_path: &str) -> Result<ImportMap, ImportMapError> {
+    let mut base_path_buf = RelativePathBuf::from(map_path);
+    base_path_buf.pop();
+
+    let json: serde_json::Value = serde_json::from_str(source).map_err(ImportMapError::InvalidJson)?;
+    let json = json.as_object().ok_or_else(|| ImportMapError::NotAnObject(String::from("import map")))?;
+
+    let imports = match json.get("imports") {
+      Some(imports) => parse_mappings(imports, "imports", &base_path_buf)?,
+      None => Vec::new(),
+    };
+
+    let mut scopes = Vec::new();
+    if let Some(scope_maps) = json.get("scopes") {
+      let scope_maps = scope_maps.as_object().ok_or_else(|| ImportMapError::NotAnObject(String::from("scopes")))?;
+      for (scope, mappings) in scope_maps.iter() {
+        scopes.push((address(scope, &base_path_buf), scope.ends_with('/'), parse_mappings(mappings, scope, &base_path_buf)?));
+      }
+    }
+    // the most specific scope is consulted first
+    scopes.sort_by_key(|(scope, _, _)| std::cmp::Reverse(address_length(scope)));
+
+    Ok(ImportMap { imports, scopes, base_path_buf })
+  }
+
+  // The scopes the importer is in come first, then the top-level imports. A specifier
+  // starting with / is resolved like the map's own keys, against the map's directory.
+  pub fn resolve(&self, importer_path: &str, specifier: &str) -> Option<ImportMapTarget> {
+    let importer = Address::Path(String::from(importer_path));
+    let specifier = if resolver::is_bare_specifier(specifier) || resolver::is_url(specifier) || specifier.starts_with('/') {
+      address(specifier, &self.base_path_buf)
+    } else {
+      Address::Path(resolver::join(importer_path, specifier))
+    };
+
+    self.scopes.iter()
+      .filter(|(scope, prefix, _)| remainder(scope, *prefix, &importer).is_some())
+      .map(|(_, _, mappings)| mappings)
+      .chain(std::iter::once(&self.imports))
+      .find_map(|mappings| resolve_in(mappings, &specifier))
+  }
+
+  // The directory of the map file, relative to the working directory
+  pub fn base_path(&self) -> &str {
+    self.base_path_buf.as_str()
+  }
+}
+
+fn parse_mappings(json: &serde_json::Value, field: &str, base_path_buf: &RelativePath) -> Result<Vec<Mapping>, ImportMapError> {
+  let json = json.as_object().ok_or_else(|| ImportMapError::NotAnObject(String::from(field)))?;
+  let mut mappings = Vec::new();
+  for (key, target) in json.iter() {
+    let target = target.as_str().ok_or_else(|| ImportMapError::InvalidTarget { key: key.clone(), target: target.to_string() })?;
+    if resolver::is_bare_specifier(target) {
+      return Err(ImportMapError::InvalidTarget { key: key.clone(), target: String::from(target) });
+    }
+    if key.ends_with('/') && !target.ends_with('/') {
+      return Err(ImportMapError::PrefixWithoutSlash { key: key.clone(), target: String::from(target) });
+    }
+    mappings.push(Mapping {
+      key: address(key, base_path_buf),
+      prefix: key.ends_with('/'),
+      target: address(target, base_path_buf),
+    });
+  }
+  // the longest matching key wins
+  mappings.sort_by_key(|mapping| std::cmp::Reverse(address_length(&mapping.key)));
+  Ok(mappings)
+}
+
+fn address(text: &str, base_path_buf: &RelativePath) -> Address {
+  if resolver::is_bare_specifier(text) {
+    Address::Bare(String::from(text))
+  } else if resolver::is_url(text) {
+    Address::Url(String::from(text))
+  } else {
+    Address::Path(base_path_buf.join_normalized(RelativePath::new(text.trim_start_matches('/'))).to_string())
+  }
+}
+
+fn address_length(address: &Address) -> usize {
+  match address {
+    Address::Path(text) | Address::Url(text) | Address::Bare(text) => text.len(),
+  }
+}
+
+// What follows a key in the specifier it matches, which is everything after a prefix key
+// and nothing after any other
+fn remainder<'a>(key: &Address, prefix: bool, specifier: &'a Address) -> Option<&'a str> {
+  match (key, specifier) {
+    (Address::Path(key), Address::Path(specifier)) if prefix => {
+      if key.is_empty() { Some(specifier.as_str()) } else { specifier.strip_prefix(key.as_str())?.strip_prefix('/') }
+    },
+    (Address::Url(key), Address::Url(specifier)) | (Address::Bare(key), Address::Bare(specifier)) if prefix => {
+      specifier.strip_prefix(key.as_str())
+    },
+    _ if key == specifier => Some(""),
+    _ => None,
+  }
+}
+
+fn resolve_in(mappings: &[Mapping], specifier: &Address) -> Option<ImportMapTarget> {
+  mappings.iter().find_map(|mapping| {
+    let remainder = remainder(&mapping.key, mapping.prefix, specifier)?;
+    Some(match &mapping.target {
+      Address::Path(target) => ImportMapTarget::Path(RelativePath::new(target).join_normalized(RelativePath::new(remainder)).to_string()),
+      Address::Url(target) => ImportMapTarget::Url(format!("{}{}", target, remainder)),
+      Address::Bare(_) => unreachable!("bare targets are rejected when the map is parsed"),
+    })
+  })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IMPORT_MAP: &str = r#"{
+        "imports": {
+            "lit": "https://cdn.example.com/lit@3/index.js",
+            "lit/": "https://cdn.example.com/lit@3/",
+            "~/": "./src/",
+            "https://cdn.example.com/old.js": "./vendor/old.js",
+            "/vendor/legacy.js": "./vendor/legacy@2.js"
+        },
+        "scopes": {
+            "/src/legacy/": {
+                "lit": "/vendor/lit@2/index.js"
+            }
+        }
+    }"#;
+
+    #[test]
+    fn resolves_exact_and_prefix_keys() {
+        let import_map = ImportMap::parse(IMPORT_MAP, "web/importmap.json").unwrap();
+        assert_eq!(import_map.resolve("web/src/main.js", "lit"), Some(ImportMapTarget::Url(String::from("https://cdn.example.com/lit@3/index.js"))));
+        assert_eq!(import_map.resolve("web/src/main.js", "lit/decorators.js"), Some(ImportMapTarget::Url(String::from("https://cdn.example.com/lit@3/decorators.js"))));
+        assert_eq!(import_map.resolve("web/src/main.js", "~/utils/date.js"), Some(ImportMapTarget::Path(String::from("web/src/utils/date.js"))));
+        assert_eq!(import_map.resolve("web/src/main.js", "https://cdn.example.com/old.js"), Some(ImportMapTarget::Path(String::from("web/vendor/old.js"))));
+        assert_eq!(import_map.resolve("web/src/main.js", "/vendor/legacy.js"), Some(ImportMapTarget::Path(String::from("web/vendor/legacy@2.js"))));
+        assert_eq!(import_map.resolve("web/main.js", "./vendor/legacy.js"), Some(ImportMapTarget::Path(String::from("web/vendor/legacy@2.js"))));
+        assert_eq!(import_map.resolve("web/src/main.js", "./lit"), None);
+        assert_eq!(import_map.resolve("web/src/main.js", "lit-html"), None);
+    }
+
+    #[test]
+    fn resolves_scoped_keys_for_importers_in_scope() {
+        let import_map = ImportMap::parse(IMPORT_MAP, "web/importmap.json").unwrap();
+        assert_eq!(import_map.resolve("web/src/legacy/chart.js", "lit"), Some(ImportMapTarget::Path(String::from("web/vendor/lit@2/index.js"))));
+        assert_eq!(import_map.resolve("web/src/legacy/chart.js", "lit/directive.js"), Some(ImportMapTarget::Url(String::from("https://cdn.example.com/lit@3/directive.js"))));
+    }
+
+    #[test]
+    fn reports_invalid_import_maps() {
+        let err = ImportMap::parse(r#"{ "imports": { "lit": "lit-html" } }"#, "importmap.json").err().unwrap();
+        assert_eq!(err.to_string(), "'lit' must map to a URL or to a path starting with ./, ../ or /, not 'lit-html'");
+        let err = ImportMap::parse(r#"{ "imports": { "lit/": "./lit" } }"#, "importmap.json").err().unwrap();
+        assert_eq!(err.to_string(), "'lit/' ends with / but './lit' does not");
+        let err = ImportMap::parse(r#"{ "scopes": [] }"#, "importmap.json").err().unwrap();
+        assert_eq!(err.to_string(), "\"scopes\" must be an object");
+    }
+}
@@ -1,20 +1,42 @@
 use std::convert::AsRef;
+use std::fmt;
 use strum_macros::AsRefStr;
 
 #[derive(Debug)]
 pub struct JavascriptModule {
   pub imports: Vec<JavascriptImport>,
   pub exports: Vec<JavascriptExport>,
+  // names declared or listed by this module itself, such as `export const a` or `export { b as c };`
+  pub local_exports: Vec<NamedExport>,
+  pub dynamic_imports: Vec<DynamicImport>,
+  pub asset_references: Vec<AssetReference>,
   pub raw_source: String,
 }
 
+impl JavascriptModule {
+  // Every name other modules can import from this one. Names that come through
+  // `export * from` are not included, since they depend on the other module.
+  pub fn exported_names(&self) -> Vec<String> {
+    let re_exported_names = self.exports.iter().flat_map(|export| {
+      export.named_exports.iter().map(|named_export| named_export.exported_name.clone()).chain(export.namespace_export.clone())
+    });
+    self.local_exports.iter().map(|named_export| named_export.exported_name.clone()).chain(re_exported_names).collect()
+  }
+}
+
 #[derive(Debug, PartialEq)]
 enum ImportToken {
   Variables, // represents a block of possible NamedImports or DefaultImports
   From,
   Specifier,
+  // after a default import, where ',' may introduce more bindings
   DefaultImport,
+  NamespaceAs,
+  NamespaceImport,
   NamedImport,
+  // after an imported name, where `as` may rename it
+  NamedImportAs { alias_required: bool },
+  NamedImportAlias,
   NextNamedImport,
   StatementEnd,
 }
@@ -22,11 +44,23 @@ enum ImportToken {
 #[derive(Debug)]
 enum ExportToken {
   Variables,
+  // after `*`, where `as` may name a namespace
+  StarAs,
+  NamespaceExport,
   From,
   Specifier,
   NamedExport,
+  // after a local name, where `as` may rename it
+  NamedExportAs,
+  NamedExportAlias,
   NextNamedExport,
   StatementEnd,
+  // after `function`, `async` or `class`
+  DeclarationName,
+  // after `const`, `let` or `var`, or a ',' between declarators
+  DeclarationBinding,
+  // anything after a declared binding, up to the next declarator or the statement end
+  DeclarationInitializer,
 }
 
 #[derive(Debug)]
@@ -44,47 +78,162 @@ struct PendingJavascriptExport {
   expected_token: ExportToken,
   token_start: Option<usize>,
   str_char: Option<char>,
+  // unclosed brackets in a declaration's initializer
+  declaration_depth: usize,
 }
 
 #[derive(Debug, PartialEq)]
 pub struct JavascriptImport {
   pub default_name: Option<String>,
   pub default_import: Option<DefaultImport>,
+  // `ns` in `import * as ns from './a.js'`
+  pub namespace_import: Option<String>,
   pub named_imports: Vec<NamedImport>,
   pub specifier: String,
   pub specifier_start: usize,
   pub specifier_end: usize,
+  // `import data from './data.json' with { type: 'json' }`
+  pub attributes: Vec<ImportAttribute>,
+  // from the `with` keyword to the closing '}', when there are attributes
+  pub attributes_start: usize,
+  pub attributes_end: usize,
+  // the module the specifier resolved to, filled in once the module graph is built.
+  // It stays None for externals.
+  pub module_id: Option<String>,
+}
+
+// `import('./lazy.js')`. Only calls whose argument is a plain string literal are recorded.
+#[derive(Debug, PartialEq)]
+pub struct DynamicImport {
+  pub specifier: String,
+  pub specifier_start: usize,
+  pub specifier_end: usize,
+  // `import('./data.json', { with: { type: 'json' } })`
+  pub attributes: Vec<ImportAttribute>,
+  // from just after the specifier's closing quote to the options' closing '}', when there
+  // are attributes
+  pub options_start: usize,
+  pub options_end: usize,
+  pub module_id: Option<String>,
+}
+
+// `new URL('./logo.png', import.meta.url)`, for relative paths only
+#[derive(Debug, PartialEq)]
+pub struct AssetReference {
+  pub specifier: String,
+  pub specifier_start: usize,
+  pub specifier_end: usize,
+  pub module_id: Option<String>,
 }
 
 #[derive(Debug, PartialEq)]
 pub struct DefaultImport {
-  variable_name: String,
-  binding_name: String
+  pub variable_name: String,
+  pub binding_name: String
 }
 
 #[derive(Debug, PartialEq)]
 pub struct NamedImport {
-  variable_name: String,
-  binding_name: String
+  // the local variable, `b` in `import { a as b }`
+  pub variable_name: String,
+  // the name exported by the imported module, `a` in `import { a as b }`
+  pub binding_name: String
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct JavascriptExport {
   pub default_name: Option<String>,
+  // `export * from './a.js'`
+  pub star_export: bool,
+  // `ns` in `export * as ns from './a.js'`
+  pub namespace_export: Option<String>,
   pub named_exports: Vec<NamedExport>,
   pub specifier: String,
   pub specifier_start: usize,
   pub specifier_end: usize,
+  pub attributes: Vec<ImportAttribute>,
+  pub attributes_start: usize,
+  pub attributes_end: usize,
+  pub module_id: Option<String>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct NamedExport {
-  variable_name: String,
-  binding_name: String // TODO: what is the difference?
+  // the name in the module being exported from, `a` in `export { a as b } from './a.js'`
+  pub local_name: String,
+  // the name other modules import, `b` in `export { a as b } from './a.js'`
+  pub exported_name: String,
+}
+
+// `type: 'json'` in `with { type: 'json' }`, or in the older `assert { type: 'json' }`
+#[derive(Debug, PartialEq)]
+pub struct ImportAttribute {
+  pub key: String,
+  pub value: String,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum LexErrorKind {
+  ExpectedIdentifier,
+  ExpectedDeclaration,
+  ExpectedCommaOrBrace,
+  ExpectedCommaAsOrBrace,
+  ExpectedFrom,
+  ExpectedAs,
+  ExpectedFromOrStatementEnd,
+  ExpectedString,
+  ExpectedStatementEnd,
+  ExpectedImportAttributes,
+  UnexpectedEndOfInput,
+}
+
+impl fmt::Display for LexErrorKind {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let description = match self {
+      LexErrorKind::ExpectedIdentifier => "expected identifier",
+      LexErrorKind::ExpectedDeclaration => "expected declaration, 'default', '{' or '*'",
+      LexErrorKind::ExpectedCommaOrBrace => "expected ',' or '}'",
+      LexErrorKind::ExpectedCommaAsOrBrace => "expected ',' or keyword 'as' or '}'",
+      LexErrorKind::ExpectedFrom => "expected keyword 'from'",
+      LexErrorKind::ExpectedAs => "expected keyword 'as'",
+      LexErrorKind::ExpectedFromOrStatementEnd => "expected keyword 'from' or statement end ';'",
+      LexErrorKind::ExpectedString => "expected string start ' or \"",
+      LexErrorKind::ExpectedStatementEnd => "expected statement end",
+      LexErrorKind::ExpectedImportAttributes => "expected import attributes like { type: 'json' }",
+      LexErrorKind::UnexpectedEndOfInput => "unexpected end of input",
+    };
+    write!(f, "{}", description)
+  }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct LexError {
+  pub kind: LexErrorKind,
+  pub found: Option<char>,
+  pub index: usize,
+  // 1-based, with the column counted in characters
+  pub line: usize,
+  pub column: usize,
+  pub file_path: Option<String>,
+  pub code_frame: String,
+}
+
+impl fmt::Display for LexError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if let Some(file_path) = &self.file_path {
+      write!(f, "{}:", file_path)?;
+    }
+    write!(f, "{}:{}: {}", self.line, self.column, self.kind)?;
+    if let Some(found) = self.found {
+      write!(f, ", found {:?}", found)?;
+    }
+    write!(f, "\n{}", self.code_frame)
+  }
 }
 
 pub struct JavascriptLexer {
   source: String,
+  file_path: Option<String>,
   current_index: usize,
   current_char: char,
   indices_to_skip: usize,
@@ -92,6 +241,12 @@ pub struct JavascriptLexer {
   current_handler: Handler,
   pending_import: Option<PendingJavascriptImport>,
   pending_export: Option<PendingJavascriptExport>,
+  string_delimiter: char,
+  in_regex_class: bool,
+  // number of unclosed '{' outside of template literals
+  brace_depth: usize,
+  // brace_depth at the start of each unclosed ${...} template substitution
+  template_depths: Vec<usize>,
 }
 
 #[derive(Clone, Copy, AsRefStr, Debug)]
@@ -99,33 +254,276 @@ enum Handler {
   Normal,
   Import,
   Export,
+  LineComment,
+  BlockComment,
+  StringLiteral,
+  TemplateLiteral,
+  RegexLiteral,
 }
 
-#[derive(Clone, Copy)]
-enum ImportTokens {
-  Variables,
-  From,
-  Specifier
+// Keywords after which a '/' begins a regular expression rather than a division
+const KEYWORDS_BEFORE_EXPRESSION: [&str; 15] = [
+  "return", "typeof", "instanceof", "in", "of", "new", "delete", "void",
+  "throw", "case", "do", "else", "yield", "await", "extends",
+];
+
+fn is_identifier_start(c: char) -> bool {
+  c.is_alphabetic() || c == '_' || c == '$'
+}
+
+fn is_identifier_part(c: char) -> bool {
+  c.is_alphanumeric() || c == '_' || c == '$' || c == '\u{200C}' || c == '\u{200D}'
+}
+
+pub fn is_identifier(name: &str) -> bool {
+  name.starts_with(is_identifier_start) && name.chars().all(is_identifier_part)
+}
+
+// Names a module, which is always strict, cannot declare as a variable
+const RESERVED_WORDS: [&str; 48] = [
+  "await", "break", "case", "catch", "class", "const", "continue", "debugger", "default",
+  "delete", "do", "else", "enum", "export", "extends", "false", "finally", "for", "function",
+  "if", "import", "in", "instanceof", "new", "null", "return", "super", "switch", "this",
+  "throw", "true", "try", "typeof", "var", "void", "while", "with", "yield", "let", "static",
+  "implements", "interface", "package", "private", "protected", "public", "arguments", "eval",
+];
+
+pub fn is_reserved_word(name: &str) -> bool {
+  RESERVED_WORDS.contains(&name)
+}
+
+// The contents of the quoted string starting at index, if it is terminated on the same line
+fn string_literal_at(source: &str, index: usize) -> Option<&str> {
+  let quote = source[index..].chars().next()?;
+  let mut escaped = false;
+  for (offset, c) in source[index + 1..].char_indices() {
+    match c {
+      '\n' => return None,
+      '\\' if !escaped => escaped = true,
+      c if c == quote && !escaped => return Some(&source[index + 1..index + 1 + offset]),
+      _ => escaped = false,
+    }
+  }
+  None
+}
+
+// An identifier or a string literal, which ES2022 allows wherever a module exports a
+// name, along with the number of characters it takes up including any quotes
+fn module_export_name_at(source: &str, index: usize) -> Option<(&str, usize)> {
+  match source[index..].chars().next()? {
+    '\'' | '"' => string_literal_at(source, index).map(|name| (name, name.chars().count() + 2)),
+    c if is_identifier_start(c) => {
+      let name = identifier_at(source, index);
+      Some((name, name.chars().count()))
+    },
+    _ => None,
+  }
+}
+
+fn identifier_at(source: &str, index: usize) -> &str {
+  let identifier_length = source[index..].find(|c: char| !is_identifier_part(c)).unwrap_or(source.len() - index);
+  &source[index..index + identifier_length]
+}
+
+fn significant_index_at(source: &str, index: usize) -> Option<usize> {
+  source[index..].find(|c: char| !c.is_whitespace()).map(|offset| index + offset)
+}
+
+// The identifier at the next non-whitespace character, or "" if there is none
+fn word_after(source: &str, index: usize) -> &str {
+  significant_index_at(source, index).map_or("", |index| identifier_at(source, index))
+}
+
+// Collects the names bound by a destructuring pattern such as `{ a, b: [c], ...d }`,
+// returning the index just past its closing bracket
+fn binding_pattern_at(source: &str, index: usize, names: &mut Vec<String>) -> Option<usize> {
+  let is_object = source[index..].starts_with('{');
+  let close = if is_object { '}' } else { ']' };
+  let mut index = index + 1;
+  loop {
+    index = significant_index_at(source, index)?;
+    if source[index..].starts_with(close) {
+      return Some(index + 1);
+    }
+    if source[index..].starts_with(',') {
+      // between elements, or a hole in an array pattern
+      index += 1;
+      continue;
+    }
+
+    if source[index..].starts_with("...") {
+      index = binding_target_at(source, significant_index_at(source, index + 3)?, names)?;
+    } else if is_object {
+      let key_end = property_key_end(source, index)?;
+      let after_key = significant_index_at(source, key_end)?;
+      if source[after_key..].starts_with(':') {
+        index = binding_target_at(source, significant_index_at(source, after_key + 1)?, names)?;
+      } else {
+        // shorthand `{ a }` binds the key itself
+        index = binding_target_at(source, index, names)?;
+      }
+    } else {
+      index = binding_target_at(source, index, names)?;
+    }
+
+    index = significant_index_at(source, index)?;
+    if source[index..].starts_with('=') {
+      index = default_value_end(source, index + 1, close)?;
+    }
+  }
+}
+
+fn binding_target_at(source: &str, index: usize, names: &mut Vec<String>) -> Option<usize> {
+  match source[index..].chars().next()? {
+    '{' | '[' => binding_pattern_at(source, index, names),
+    c if is_identifier_start(c) => {
+      let name = identifier_at(source, index);
+      names.push(String::from(name));
+      Some(index + name.len())
+    },
+    _ => None,
+  }
+}
+
+fn property_key_end(source: &str, index: usize) -> Option<usize> {
+  match source[index..].chars().next()? {
+    '\'' | '"' => string_literal_at(source, index).map(|key| index + key.len() + 2),
+    // computed key, `{ [key]: a }`
+    '[' => default_value_end(source, index + 1, ']').map(|end| end + 1),
+    // identifiers and numeric keys
+    c if is_identifier_part(c) => Some(index + identifier_at(source, index).len()),
+    _ => None,
+  }
+}
+
+// The index of the ',' or closing bracket that ends a default value inside a pattern
+fn default_value_end(source: &str, index: usize, close: char) -> Option<usize> {
+  let mut depth = 0;
+  let mut chars = source[index..].char_indices().map(|(offset, c)| (index + offset, c));
+  while let Some((i, c)) = chars.next() {
+    match c {
+      ',' if depth == 0 => return Some(i),
+      c if c == close && depth == 0 => return Some(i),
+      '(' | '[' | '{' => depth += 1,
+      ')' | ']' | '}' => depth -= 1,
+      '\'' | '"' => {
+        let string_end = i + string_literal_at(source, i)?.len() + 1;
+        chars.find(|&(i, _)| i == string_end);
+      },
+      '`' => {
+        let template_end = i + 1 + source[i + 1..].find('`')?;
+        chars.find(|&(i, _)| i == template_end);
+      },
+      _ => {},
+    }
+  }
+  None
+}
+
+// The `{ type: 'json' }` that follows `with` or `assert`, along with the index just past it
+fn import_attributes_at(source: &str, index: usize) -> Option<(Vec<ImportAttribute>, usize)> {
+  let mut index = significant_index_at(source, index)?;
+  if !source[index..].starts_with('{') {
+    return None;
+  }
+
+  let mut attributes = Vec::new();
+  loop {
+    // just past the '{' or a ','
+    index = significant_index_at(source, index + 1)?;
+    if source[index..].starts_with('}') {
+      return Some((attributes, index + 1));
+    }
+
+    let key_end = property_key_end(source, index)?;
+    let key = source[index..key_end].trim_matches(['\'', '"']);
+    index = significant_index_at(source, key_end)?;
+    if !source[index..].starts_with(':') {
+      return None;
+    }
+    index = significant_index_at(source, index + 1)?;
+    if !source[index..].starts_with(['\'', '"']) {
+      return None;
+    }
+    let value = string_literal_at(source, index)?;
+    attributes.push(ImportAttribute {
+      key: String::from(key),
+      value: String::from(value),
+    });
+
+    index = significant_index_at(source, index + value.len() + 2)?;
+    match source[index..].chars().next()? {
+      ',' => {},
+      '}' => return Some((attributes, index + 1)),
+      _ => return None,
+    }
+  }
+}
+
+// `{ with: { type: 'json' } }`, the second argument of import(), along with the index of its
+// closing '}'
+fn dynamic_import_options_at(source: &str, index: usize) -> Option<(Vec<ImportAttribute>, usize)> {
+  let mut index = significant_index_at(source, index)?;
+  if !source[index..].starts_with('{') {
+    return None;
+  }
+  index = significant_index_at(source, index + 1)?;
+  let key_end = property_key_end(source, index)?;
+  let key = source[index..key_end].trim_matches(['\'', '"']);
+  if key != "with" && key != "assert" {
+    return None;
+  }
+  index = significant_index_at(source, key_end)?;
+  if !source[index..].starts_with(':') {
+    return None;
+  }
+  let (attributes, attributes_end) = import_attributes_at(source, index + 1)?;
+  index = significant_index_at(source, attributes_end)?;
+  if source[index..].starts_with(',') {
+    index = significant_index_at(source, index + 1)?;
+  }
+  if source[index..].starts_with('}') { Some((attributes, index)) } else { None }
+}
+
+// Whether a line break between the two indices continues the expression before it rather
+// than ending the statement, following automatic semicolon insertion
+fn continues_expression(source: &str, before: usize, after: usize) -> bool {
+  source[..before].trim_end().ends_with(|c: char| "=,([{?:+-*/%&|^<>!~".contains(c))
+    || source[after..].trim_start().starts_with(|c: char| ".,?:+-*/%=&|^<>([`".contains(c))
 }
 
 impl JavascriptLexer {
   pub fn new(source: String) -> JavascriptLexer {
     JavascriptLexer {
       source,
+      file_path: None,
       current_index: 0,
       current_char: ' ',
       indices_to_skip: 0,
       handler_stack: Vec::new(),
       current_handler: Handler::Normal,
       pending_import: None,
-      pending_export: None
+      pending_export: None,
+      string_delimiter: '"',
+      in_regex_class: false,
+      brace_depth: 0,
+      template_depths: Vec::new(),
     }
   }
 
-  pub fn parse_module(&mut self) -> JavascriptModule {
+  // The path is only used to point errors at the file they came from
+  pub fn with_file_path(mut self, file_path: String) -> JavascriptLexer {
+    self.file_path = Some(file_path);
+    self
+  }
+
+  pub fn parse_module(&mut self) -> Result<JavascriptModule, LexError> {
     let mut js_module = JavascriptModule {
       imports: Vec::new(),
       exports: Vec::new(),
+      local_exports: Vec::new(),
+      dynamic_imports: Vec::new(),
+      asset_references: Vec::new(),
       raw_source: self.source.clone(),
     };
 
@@ -135,28 +533,89 @@ impl JavascriptLexer {
     self.indices_to_skip = 0;
     self.handler_stack = Vec::new();
     self.handler_stack.push(Handler::Normal);
+    self.brace_depth = 0;
+    self.template_depths = Vec::new();
 
     let source = self.source.clone();
 
     for (i, c) in source.char_indices() {
       if self.indices_to_skip > 0 {
-        self.indices_to_skip = self.indices_to_skip - 1;
+        self.indices_to_skip -= 1;
         continue
       }
 
       self.current_char = c;
       self.current_index = i;
 
-      self.current_handler = self.handler_stack.pop().expect(format!("JavascriptLexer died - no handler specified at index {}. Last handler was {}", i, self.current_handler.as_ref()).as_str());
+      self.current_handler = self.handler_stack.pop().unwrap_or_else(|| panic!("JavascriptLexer died - no handler specified at index {}. Last handler was {}", i, self.current_handler.as_ref()));
 
       match self.current_handler {
         Handler::Normal => self.handle_normal(&mut js_module),
-        Handler::Import => self.handle_import(&mut js_module),
-        Handler::Export => self.handle_export(&mut js_module),
+        Handler::LineComment => self.handle_line_comment(),
+        Handler::BlockComment => self.handle_block_comment(),
+        Handler::StringLiteral => self.handle_string_literal(),
+        Handler::TemplateLiteral => self.handle_template_literal(),
+        Handler::RegexLiteral => self.handle_regex_literal(),
+        Handler::Import => self.handle_import(&mut js_module)?,
+        Handler::Export => self.handle_export(&mut js_module)?,
       }
     }
 
-    js_module
+    self.handle_end_of_input(&mut js_module)?;
+
+    Ok(js_module)
+  }
+
+  fn handle_end_of_input(&mut self, js_module: &mut JavascriptModule) -> Result<(), LexError> {
+    self.current_index = self.source.len();
+
+    if let Some(pending_import) = self.pending_import.take() {
+      match pending_import.expected_token {
+        ImportToken::StatementEnd => js_module.imports.push(pending_import.import),
+        _ => return Err(self.error(LexErrorKind::UnexpectedEndOfInput)),
+      }
+    }
+
+    if let Some(pending_export) = self.pending_export.take() {
+      match pending_export.expected_token {
+        // `export { b }` never reached a specifier, so it exports local variables
+        ExportToken::From => js_module.local_exports.extend(pending_export.export.named_exports),
+        // declared names are recorded as soon as they are read
+        ExportToken::Variables | ExportToken::DeclarationBinding | ExportToken::DeclarationInitializer => {},
+        ExportToken::StatementEnd => js_module.exports.push(pending_export.export),
+        _ => return Err(self.error(LexErrorKind::UnexpectedEndOfInput)),
+      }
+    }
+
+    Ok(())
+  }
+
+  fn error(&self, kind: LexErrorKind) -> LexError {
+    let line_start = self.source[..self.current_index].rfind('\n').map_or(0, |newline| newline + 1);
+    let line_end = self.source[self.current_index..].find('\n').map_or(self.source.len(), |newline| self.current_index + newline);
+    let line = self.source[..line_start].matches('\n').count() + 1;
+    let column = self.source[line_start..self.current_index].chars().count() + 1;
+
+    let line_number = line.to_string();
+    let gutter = " ".repeat(line_number.len());
+    let code_frame = format!(
+      "{} |\n{} | {}\n{} | {}^",
+      gutter,
+      line_number,
+      self.source[line_start..line_end].trim_end_matches('\r'),
+      gutter,
+      " ".repeat(column - 1),
+    );
+
+    LexError {
+      kind,
+      found: self.source[self.current_index..].chars().next(),
+      index: self.current_index,
+      line,
+      column,
+      file_path: self.file_path.clone(),
+      code_frame,
+    }
   }
 
   fn keep_using_handler(&mut self) {
@@ -167,11 +626,19 @@ impl JavascriptLexer {
     self.handler_stack.push(handler);
   }
 
-  fn handle_normal(&mut self, _js_module: &mut JavascriptModule) {
+  fn handle_normal(&mut self, js_module: &mut JavascriptModule) {
     match self.current_char {
-      'i' => {
-        match self.source.get(self.current_index..self.current_index + 6).unwrap_or("") {
-          "import" => {
+      'i' if self.is_keyword_at_current_index("import") => {
+        match self.next_significant_char(self.current_index + 6) {
+          // import('./a.js') and import.meta are expressions, not import statements
+          Some('(') => {
+            self.record_expression_reference(js_module);
+            self.keep_using_handler();
+          },
+          Some('.') => {
+            self.keep_using_handler();
+          },
+          _ => {
             self.queue_handler(Handler::Import);
             self.indices_to_skip = 5;
             self.pending_import = Some(PendingJavascriptImport {
@@ -180,50 +647,318 @@ impl JavascriptLexer {
                 default_name: None,
                 named_imports: Vec::new(),
                 default_import: None,
+                namespace_import: None,
                 specifier: String::new(),
                 specifier_start: 0,
                 specifier_end: 0,
+                attributes: Vec::new(),
+                attributes_start: 0,
+                attributes_end: 0,
+                module_id: None,
               },
               token_start: None,
               str_char: None,
             });
-          },
-          _ => {
-            self.keep_using_handler();
           }
         }
       },
-      'e' => {
-        match self.source.get(self.current_index..self.current_index + 6).unwrap_or("") {
-          "export" => {
-            self.queue_handler(Handler::Export);
-            self.indices_to_skip = 5;
-            self.pending_export = Some(PendingJavascriptExport {
-              expected_token: ExportToken::Variables,
-              export: JavascriptExport {
-                default_name: None,
-                named_exports: Vec::new(),
-                specifier: String::new(),
-                specifier_start: 0,
-                specifier_end: 0,
-              },
-              token_start: None,
-              str_char: None,
-            });
+      'e' if self.is_keyword_at_current_index("export") => {
+        self.queue_handler(Handler::Export);
+        self.indices_to_skip = 5;
+        self.pending_export = Some(PendingJavascriptExport {
+          expected_token: ExportToken::Variables,
+          export: JavascriptExport {
+            default_name: None,
+            star_export: false,
+            namespace_export: None,
+            named_exports: Vec::new(),
+            specifier: String::new(),
+            specifier_start: 0,
+            specifier_end: 0,
+            attributes: Vec::new(),
+            attributes_start: 0,
+            attributes_end: 0,
+            module_id: None,
+          },
+          token_start: None,
+          str_char: None,
+          declaration_depth: 0,
+        });
+      },
+      'n' if self.is_keyword_at_current_index("new") => {
+        self.record_expression_reference(js_module);
+        self.keep_using_handler();
+      },
+      '/' | '\'' | '"' | '`' => {
+        self.keep_using_handler();
+        self.queue_literal_or_comment_handler();
+      },
+      '{' => {
+        self.brace_depth += 1;
+        self.keep_using_handler();
+      },
+      '}' => {
+        if self.template_depths.last() == Some(&self.brace_depth) {
+          // end of a ${...} substitution, so return to the template literal underneath
+          self.template_depths.pop();
+        } else {
+          self.brace_depth = self.brace_depth.saturating_sub(1);
+          self.keep_using_handler();
+        }
+      },
+      _ => {
+        self.keep_using_handler();
+      }
+    }
+  }
+
+  // Queues the handler that skips the comment, string, template or regular expression
+  // starting at the current character. A '/' that is a division queues nothing.
+  fn queue_literal_or_comment_handler(&mut self) {
+    match self.current_char {
+      '/' => {
+        match self.source[self.current_index + 1..].chars().next() {
+          Some('/') => {
+            self.queue_handler(Handler::LineComment);
+          },
+          Some('*') => {
+            self.queue_handler(Handler::BlockComment);
+            self.indices_to_skip = 1;
+          },
+          _ if self.is_regex_allowed() => {
+            self.in_regex_class = false;
+            self.queue_handler(Handler::RegexLiteral);
           },
           _ => {
-            self.keep_using_handler();
+            // division
           }
         }
       },
+      '\'' | '"' => {
+        self.string_delimiter = self.current_char;
+        self.queue_handler(Handler::StringLiteral);
+      },
+      '`' => {
+        self.queue_handler(Handler::TemplateLiteral);
+      },
+      _ => {}
+    }
+  }
+
+  // Comments, strings, templates and regular expressions are skipped over so that
+  // their contents are never mistaken for import or export statements. Each of them
+  // finishes by not re-queueing itself, which returns control to the handler below it.
+  // The line break is left to the handler underneath, since it can end a statement
+  fn handle_line_comment(&mut self) {
+    if !self.source[self.current_index + self.current_char.len_utf8()..].starts_with('\n') {
+      self.keep_using_handler();
+    }
+  }
+
+  fn handle_block_comment(&mut self) {
+    if self.source[self.current_index..].starts_with("*/") {
+      self.indices_to_skip = 1;
+    } else {
+      self.keep_using_handler();
+    }
+  }
+
+  fn handle_string_literal(&mut self) {
+    match self.current_char {
+      '\\' => {
+        self.indices_to_skip = 1;
+        self.keep_using_handler();
+      },
+      // an unescaped line break means the string was never terminated
+      c if c == self.string_delimiter || c == '\n' => {},
+      _ => {
+        self.keep_using_handler();
+      }
+    }
+  }
+
+  fn handle_template_literal(&mut self) {
+    match self.current_char {
+      '\\' => {
+        self.indices_to_skip = 1;
+        self.keep_using_handler();
+      },
+      '`' => {},
+      '$' if self.source[self.current_index..].starts_with("${") => {
+        self.indices_to_skip = 1;
+        self.keep_using_handler();
+        self.template_depths.push(self.brace_depth);
+        self.queue_handler(Handler::Normal);
+      },
+      _ => {
+        self.keep_using_handler();
+      }
+    }
+  }
+
+  fn handle_regex_literal(&mut self) {
+    match self.current_char {
+      '\\' => {
+        self.indices_to_skip = 1;
+        self.keep_using_handler();
+      },
+      '[' => {
+        self.in_regex_class = true;
+        self.keep_using_handler();
+      },
+      ']' => {
+        self.in_regex_class = false;
+        self.keep_using_handler();
+      },
+      '/' if !self.in_regex_class => {},
+      '\n' => {},
       _ => {
         self.keep_using_handler();
       }
     }
   }
 
-  fn handle_import(&mut self, js_module: &mut JavascriptModule) {
-    let mut pending_import = self.pending_import.as_mut().unwrap();
+  fn is_keyword_at_current_index(&self, keyword: &str) -> bool {
+    let keyword_end = self.current_index + keyword.len();
+    self.source[self.current_index..].starts_with(keyword)
+      // `reimport` and `obj.export` are not keywords
+      && !self.source[..self.current_index].chars().next_back().is_some_and(|c| is_identifier_part(c) || c == '.')
+      && !self.source[keyword_end..].chars().next().is_some_and(is_identifier_part)
+      // `{ import: ... }` is a property name
+      && self.next_significant_char(keyword_end) != Some(':')
+  }
+
+  // `with { ... }`, or `assert { ... }` which must stay on the same line as the specifier,
+  // along with the index of the closing '}'
+  fn import_attributes_at_current_index(&mut self) -> Result<(Vec<ImportAttribute>, usize), LexError> {
+    let keyword = identifier_at(&self.source, self.current_index);
+    if keyword != "with" && keyword != "assert" {
+      return Err(self.error(LexErrorKind::ExpectedStatementEnd));
+    }
+    let (attributes, attributes_end) = match import_attributes_at(&self.source, self.current_index + keyword.len()) {
+      Some(attributes) => attributes,
+      None => return Err(self.error(LexErrorKind::ExpectedImportAttributes)),
+    };
+    self.indices_to_skip = self.source[self.current_index..attributes_end].chars().count() - 1;
+    Ok((attributes, attributes_end - 1))
+  }
+
+  fn is_comment_at_current_index(&self) -> bool {
+    self.source[self.current_index..].starts_with("//") || self.source[self.current_index..].starts_with("/*")
+  }
+
+  // A block comment containing a line break counts as one for automatic semicolon insertion
+  fn is_multiline_comment_at_current_index(&self) -> bool {
+    let comment = &self.source[self.current_index..];
+    comment.starts_with("/*") && comment[2..].find("*/").is_none_or(|end| comment[2..2 + end].contains('\n'))
+  }
+
+  fn next_significant_char(&self, index: usize) -> Option<char> {
+    self.next_significant_index(index).and_then(|index| self.source[index..].chars().next())
+  }
+
+  fn next_significant_index(&self, index: usize) -> Option<usize> {
+    significant_index_at(&self.source, index)
+  }
+
+  // import('./a.js') or new URL('./a.png', import.meta.url) at the current index, which can
+  // be anywhere an expression can, including the initializer of an exported declaration
+  fn record_expression_reference(&self, js_module: &mut JavascriptModule) {
+    if self.is_keyword_at_current_index("import") && self.next_significant_char(self.current_index + 6) == Some('(') {
+      js_module.dynamic_imports.extend(self.dynamic_import_at_current_index());
+    } else if self.is_keyword_at_current_index("new") {
+      js_module.asset_references.extend(self.asset_reference_at_current_index());
+    }
+  }
+
+  // import( './lazy.js' ) or import("./lazy.js", { with: ... }), starting from the `import` keyword
+  fn dynamic_import_at_current_index(&self) -> Option<DynamicImport> {
+    let open_paren = self.next_significant_index(self.current_index + 6)?;
+    let quote = self.next_significant_index(open_paren + 1)?;
+    if !self.source[quote..].starts_with(['\'', '"']) {
+      return None;
+    }
+    let specifier = string_literal_at(&self.source, quote)?;
+    let specifier_end = quote + specifier.len();
+    let (attributes, options_end) = match self.next_significant_char(specifier_end + 2)? {
+      ')' => (Vec::new(), 0),
+      // options that are not an object literal with attributes are left as they are
+      ',' => dynamic_import_options_at(&self.source, self.next_significant_index(specifier_end + 2)? + 1).unwrap_or((Vec::new(), 0)),
+      _ => return None,
+    };
+    Some(DynamicImport {
+      specifier: String::from(specifier),
+      specifier_start: quote + 1,
+      specifier_end,
+      options_start: if attributes.is_empty() { 0 } else { specifier_end + 2 },
+      options_end,
+      attributes,
+      module_id: None,
+    })
+  }
+
+  // new URL('./logo.png', import.meta.url), starting from the `new` keyword
+  fn asset_reference_at_current_index(&self) -> Option<AssetReference> {
+    let url = self.next_significant_index(self.current_index + 3)?;
+    let open_paren = self.next_significant_index(url + 3)?;
+    if identifier_at(&self.source, url) != "URL" || !self.source[open_paren..].starts_with('(') {
+      return None;
+    }
+    let quote = self.next_significant_index(open_paren + 1)?;
+    if !self.source[quote..].starts_with(['\'', '"']) {
+      return None;
+    }
+    let specifier = string_literal_at(&self.source, quote)?;
+    if !specifier.starts_with("./") && !specifier.starts_with("../") {
+      return None;
+    }
+    let comma = self.next_significant_index(quote + specifier.len() + 2)?;
+    let base = self.next_significant_index(comma + 1)?;
+    if !self.source[comma..].starts_with(',') || !self.source[base..].starts_with("import.meta.url") {
+      return None;
+    }
+    match self.next_significant_char(base + "import.meta.url".len()) {
+      Some(')') => Some(AssetReference {
+        specifier: String::from(specifier),
+        specifier_start: quote + 1,
+        specifier_end: quote + specifier.len(),
+        module_id: None,
+      }),
+      _ => None,
+    }
+  }
+
+  // A '/' starts a regular expression unless it follows something that ends an
+  // expression, such as an identifier, a literal or a closing parenthesis
+  fn is_regex_allowed(&self) -> bool {
+    let preceding = self.source[..self.current_index].trim_end();
+    match preceding.chars().next_back() {
+      None => true,
+      Some(')') | Some(']') | Some('\'') | Some('"') | Some('`') => false,
+      Some(c) if is_identifier_part(c) => {
+        let word_start = preceding.rfind(|c: char| !is_identifier_part(c)).map_or(0, |index| index + 1);
+        KEYWORDS_BEFORE_EXPRESSION.contains(&&preceding[word_start..])
+      },
+      Some(_) => true,
+    }
+  }
+
+  fn handle_import(&mut self, js_module: &mut JavascriptModule) -> Result<(), LexError> {
+    let pending_import = self.pending_import.as_ref().unwrap();
+    let in_specifier = pending_import.expected_token == ImportToken::Specifier && pending_import.token_start.is_some();
+    if !in_specifier && self.is_comment_at_current_index() {
+      if pending_import.expected_token == ImportToken::StatementEnd && self.is_multiline_comment_at_current_index() {
+        js_module.imports.push(self.pending_import.take().unwrap().import);
+        self.current_handler = Handler::Normal;
+        self.handle_normal(js_module);
+      } else {
+        self.keep_using_handler();
+        self.queue_literal_or_comment_handler();
+      }
+      return Ok(());
+    }
+
+    let pending_import = self.pending_import.as_mut().unwrap();
 
     match pending_import.expected_token {
       ImportToken::Variables => {
@@ -231,10 +966,23 @@ impl JavascriptLexer {
           '{' => {
             pending_import.expected_token = ImportToken::NamedImport;
           },
-          '\u{0041}'..='\u{2FA1D}' => {
+          '*' => {
+            pending_import.expected_token = ImportToken::NamespaceAs;
+          },
+          c if is_identifier_start(c) => {
+            let identifier = identifier_at(&self.source, self.current_index);
+            self.indices_to_skip = identifier.chars().count() - 1;
+            pending_import.import.default_import = Some(DefaultImport {
+              variable_name: String::from(identifier),
+              binding_name: String::from(identifier),
+            });
             pending_import.expected_token = ImportToken::DefaultImport;
-            pending_import.token_start = Some(self.current_index);
-          }
+          },
+          '\'' | '"' if pending_import.import.default_import.is_none() => {
+            // side effect only: `import './polyfill.js';`
+            pending_import.expected_token = ImportToken::Specifier;
+            return self.handle_import(js_module);
+          },
           _ => {
               // is_whitespace
           }
@@ -244,72 +992,88 @@ impl JavascriptLexer {
       },
       ImportToken::DefaultImport => {
         match self.current_char {
-          '\u{0030}'..='\u{E01EF}' => {}, // still parsing the DefaultImport
+          c if c.is_whitespace() => {},
+          ',' => {
+            // import A, { ... } from
+            pending_import.expected_token = ImportToken::Variables;
+          },
           _ => {
-            let identifier = String::from(self.source.get(pending_import.token_start.unwrap()..self.current_index).unwrap());
-            let default_import = DefaultImport {
-              variable_name: identifier.clone(),
-              binding_name: identifier,
-            };
-            pending_import.import.default_import = Some(default_import);
-            let next_token;
-            match self.current_char {
-              ',' => {
-                // import A, { ... } from
-                // TODO: None?
-                pending_import.token_start = None;
-                next_token = ImportToken::Variables;
-              },
-              _ => {
-                // whitespace
-                next_token = ImportToken::From;
-                pending_import.token_start = None;
-              }
-            }
-            pending_import.expected_token = next_token;
+            pending_import.expected_token = ImportToken::From;
+            return self.handle_import(js_module);
           }
         }
         self.keep_using_handler();
       },
       ImportToken::NamedImport => {
-        if pending_import.token_start.is_none() {
-          match self.current_char {
-            c if c.is_whitespace() => {},
-            '\u{0041}'..='\u{2FA1D}' => {
-              pending_import.token_start = Some(self.current_index);
-            },
-            _ => {
-              panic!(format!("Invalid character '{}' at index {} - expected identifier start", self.current_char, self.current_index));
-            }
+        match self.current_char {
+          c if c.is_whitespace() => {},
+          '}' => {
+            // `import {} from` or a trailing comma
+            pending_import.expected_token = ImportToken::From;
+          },
+          c if is_identifier_start(c) => {
+            let identifier = identifier_at(&self.source, self.current_index);
+            self.indices_to_skip = identifier.chars().count() - 1;
+            pending_import.import.named_imports.push(NamedImport {
+              variable_name: String::from(identifier),
+              binding_name: String::from(identifier),
+            });
+            // `default` can never be a local variable, so it must be renamed
+            pending_import.expected_token = ImportToken::NamedImportAs { alias_required: identifier == "default" };
+          },
+          '\'' | '"' => {
+            // `import { "a-b" as ab } from`
+            let name = match string_literal_at(&self.source, self.current_index) {
+              Some(name) => name,
+              None => return Err(self.error(LexErrorKind::ExpectedString)),
+            };
+            self.indices_to_skip = name.chars().count() + 1;
+            pending_import.import.named_imports.push(NamedImport {
+              variable_name: String::from(name),
+              binding_name: String::from(name),
+            });
+            pending_import.expected_token = ImportToken::NamedImportAs { alias_required: true };
+          },
+          _ => {
+            return Err(self.error(LexErrorKind::ExpectedIdentifier));
           }
-        } else {
-          match self.current_char {
-            '\u{0030}'..='\u{E01EF}' => {
-               // still parsing the NamedImport
-            },
-            _ => {
-              let identifier = String::from(self.source.get(pending_import.token_start.unwrap()..self.current_index).unwrap());
-              let named_import = NamedImport {
-                variable_name: identifier.clone(),
-                binding_name: identifier,
-              };
-              pending_import.import.named_imports.push(named_import);
-              let next_token;
-              match self.current_char {
-                '}' => {
-                  next_token = ImportToken::From;
-                },
-                ',' => {
-                  next_token = ImportToken::NamedImport;
-                },
-                _ => {
-                  // whitespace
-                  next_token = ImportToken::NextNamedImport;
-                }
-              }
-              pending_import.expected_token = next_token;
-              pending_import.token_start = None;
-            }
+        }
+        self.keep_using_handler();
+      },
+      ImportToken::NamedImportAs { alias_required } => {
+        match self.current_char {
+          c if c.is_whitespace() => {},
+          'a' if identifier_at(&self.source, self.current_index) == "as" => {
+            self.indices_to_skip = 1;
+            pending_import.expected_token = ImportToken::NamedImportAlias;
+          },
+          _ if alias_required => {
+            return Err(self.error(LexErrorKind::ExpectedAs));
+          },
+          '}' => {
+            pending_import.expected_token = ImportToken::From;
+          },
+          ',' => {
+            pending_import.expected_token = ImportToken::NamedImport;
+          },
+          _ => {
+            return Err(self.error(LexErrorKind::ExpectedCommaAsOrBrace));
+          }
+        }
+        self.keep_using_handler();
+      },
+      ImportToken::NamedImportAlias => {
+        match self.current_char {
+          c if c.is_whitespace() => {},
+          c if is_identifier_start(c) => {
+            let identifier = identifier_at(&self.source, self.current_index);
+            self.indices_to_skip = identifier.chars().count() - 1;
+            let named_import = pending_import.import.named_imports.last_mut().unwrap();
+            named_import.variable_name = String::from(identifier);
+            pending_import.expected_token = ImportToken::NextNamedImport;
+          },
+          _ => {
+            return Err(self.error(LexErrorKind::ExpectedIdentifier));
           }
         }
         self.keep_using_handler();
@@ -324,7 +1088,35 @@ impl JavascriptLexer {
           },
           c if c.is_whitespace() => {},
           _ => {
-            panic!(format!("Invalid character '{}' at index {} - expected ',' or '}}'", self.current_char, self.current_index));
+            return Err(self.error(LexErrorKind::ExpectedCommaOrBrace));
+          }
+        }
+        self.keep_using_handler();
+      },
+      ImportToken::NamespaceAs => {
+        match self.current_char {
+          c if c.is_whitespace() => {},
+          'a' if identifier_at(&self.source, self.current_index) == "as" => {
+            self.indices_to_skip = 1;
+            pending_import.expected_token = ImportToken::NamespaceImport;
+          },
+          _ => {
+            return Err(self.error(LexErrorKind::ExpectedAs));
+          }
+        }
+        self.keep_using_handler();
+      },
+      ImportToken::NamespaceImport => {
+        match self.current_char {
+          c if c.is_whitespace() => {},
+          c if is_identifier_start(c) => {
+            let identifier = identifier_at(&self.source, self.current_index);
+            self.indices_to_skip = identifier.chars().count() - 1;
+            pending_import.import.namespace_import = Some(String::from(identifier));
+            pending_import.expected_token = ImportToken::From;
+          },
+          _ => {
+            return Err(self.error(LexErrorKind::ExpectedIdentifier));
           }
         }
         self.keep_using_handler();
@@ -339,12 +1131,12 @@ impl JavascriptLexer {
                 pending_import.expected_token = ImportToken::Specifier;
               },
               _ => {
-                panic!(format!("Invalid character '{}' at index {} - expected keyword 'from'", self.current_char, self.current_index));
+                return Err(self.error(LexErrorKind::ExpectedFrom));
               }
             }
           },
           _ => {
-            panic!(format!("Invalid character '{}' at index {} - expected keyword 'from'", self.current_char, self.current_index));
+            return Err(self.error(LexErrorKind::ExpectedFrom));
           }
         }
         self.keep_using_handler();
@@ -360,121 +1152,230 @@ impl JavascriptLexer {
               pending_import.import.specifier_start = self.current_index + 1;
             },
             _ => {
-              panic!(format!("Invalid character '{}' at index {} - expected string start ' or \"", self.current_char, self.current_index));
+              return Err(self.error(LexErrorKind::ExpectedString));
             }
           }
-        } else {
-          match self.current_char {
-            c if c == pending_import.str_char.unwrap() => {
-              pending_import.import.specifier = String::from(self.source.get(pending_import.token_start.unwrap()..self.current_index).unwrap());
-              pending_import.expected_token = ImportToken::StatementEnd;
-              pending_import.import.specifier_end = self.current_index - 1;
-            },
-            _ => {}
+        } else {
+          match self.current_char {
+            c if c == pending_import.str_char.unwrap() => {
+              pending_import.import.specifier = String::from(self.source.get(pending_import.import.specifier_start..self.current_index).unwrap());
+              pending_import.expected_token = ImportToken::StatementEnd;
+              pending_import.import.specifier_end = self.current_index - 1;
+            },
+            _ => {}
+          }
+        }
+        self.keep_using_handler();
+      },
+      ImportToken::StatementEnd => {
+        match self.current_char {
+          // only import attributes can continue an import after its specifier
+          ';' => {
+            js_module.imports.push(self.pending_import.take().unwrap().import);
+            self.queue_handler(Handler::Normal);
+            return Ok(());
+          },
+          '\n' if word_after(&self.source, self.current_index) != "with" => {
+            js_module.imports.push(self.pending_import.take().unwrap().import);
+            self.queue_handler(Handler::Normal);
+            return Ok(());
+          },
+          c if c.is_whitespace() => {},
+          c if is_identifier_start(c) && pending_import.import.attributes.is_empty() => {
+            let (attributes, attributes_end) = self.import_attributes_at_current_index()?;
+            let import = &mut self.pending_import.as_mut().unwrap().import;
+            import.attributes = attributes;
+            import.attributes_start = self.current_index;
+            import.attributes_end = attributes_end;
+          },
+          _ => {
+            return Err(self.error(LexErrorKind::ExpectedStatementEnd));
+          }
+        }
+        self.keep_using_handler();
+      },
+    }
+
+    Ok(())
+  }
+
+  fn handle_export(&mut self, js_module: &mut JavascriptModule) -> Result<(), LexError> {
+    let pending_export = self.pending_export.as_ref().unwrap();
+    // declaration initializers skip their own comments
+    let skips_comments = match pending_export.expected_token {
+      ExportToken::Specifier => pending_export.token_start.is_none(),
+      ExportToken::DeclarationInitializer => false,
+      _ => true,
+    };
+    if skips_comments && self.is_comment_at_current_index() {
+      if matches!(pending_export.expected_token, ExportToken::StatementEnd) && self.is_multiline_comment_at_current_index() {
+        js_module.exports.push(self.pending_export.take().unwrap().export);
+        self.current_handler = Handler::Normal;
+        self.handle_normal(js_module);
+      } else {
+        self.keep_using_handler();
+        self.queue_literal_or_comment_handler();
+      }
+      return Ok(());
+    }
+    if matches!(pending_export.expected_token, ExportToken::DeclarationInitializer) {
+      self.record_expression_reference(js_module);
+    }
+
+    let pending_export = self.pending_export.as_mut().unwrap();
+
+    match pending_export.expected_token {
+      ExportToken::Variables => {
+        match self.current_char {
+          '{' => {
+            pending_export.expected_token = ExportToken::NamedExport;
+          },
+          '*' => {
+            pending_export.expected_token = ExportToken::StarAs;
+          },
+          c if c.is_whitespace() => {},
+          c if is_identifier_start(c) && identifier_at(&self.source, self.current_index) == "default" => {
+            // `export default <expression>`, where the expression is left to handle_normal
+            self.indices_to_skip = 6;
+            js_module.local_exports.push(NamedExport {
+              local_name: String::from("default"),
+              exported_name: String::from("default"),
+            });
+            self.pending_export = None;
+            self.queue_handler(Handler::Normal);
+            return Ok(());
+          },
+          c if is_identifier_start(c) => {
+            let keyword = identifier_at(&self.source, self.current_index);
+            self.indices_to_skip = keyword.len() - 1;
+            pending_export.expected_token = match keyword {
+              "const" | "let" | "var" => ExportToken::DeclarationBinding,
+              "function" | "async" | "class" => ExportToken::DeclarationName,
+              _ => return Err(self.error(LexErrorKind::ExpectedDeclaration)),
+            };
+          },
+          _ => {
+            // anything else is not an export we can make sense of, so hand it back to handle_normal
+            self.pending_export = None;
+            self.current_handler = Handler::Normal;
+            self.handle_normal(js_module);
+            return Ok(());
+          }
+        }
+
+        self.keep_using_handler();
+      },
+      ExportToken::StarAs => {
+        match self.current_char {
+          c if c.is_whitespace() => {},
+          'a' if identifier_at(&self.source, self.current_index) == "as" => {
+            self.indices_to_skip = 1;
+            pending_export.expected_token = ExportToken::NamespaceExport;
+          },
+          _ => {
+            pending_export.export.star_export = true;
+            pending_export.expected_token = ExportToken::From;
+            return self.handle_export(js_module);
+          }
+        }
+        self.keep_using_handler();
+      },
+      ExportToken::NamespaceExport => {
+        match self.current_char {
+          c if c.is_whitespace() => {},
+          _ => {
+            let (name, length) = match module_export_name_at(&self.source, self.current_index) {
+              Some(name_and_length) => name_and_length,
+              None => return Err(self.error(LexErrorKind::ExpectedIdentifier)),
+            };
+            self.indices_to_skip = length - 1;
+            pending_export.export.namespace_export = Some(String::from(name));
+            pending_export.expected_token = ExportToken::From;
           }
         }
         self.keep_using_handler();
       },
-      ImportToken::StatementEnd => {
+      ExportToken::NamedExport => {
         match self.current_char {
-          c if c == ' ' => {},
-          ';' | '\n' | '\r' => {
-            js_module.imports.push(self.pending_import.take().unwrap().import);
-            self.queue_handler(Handler::Normal);
+          c if c.is_whitespace() => {},
+          '}' => {
+            // `export {} from` or a trailing comma
+            pending_export.expected_token = ExportToken::From;
           },
           _ => {
-            panic!(format!("Invalid character '{}' at index {} - expected statement end", self.current_char, self.current_index));
+            let (name, length) = match module_export_name_at(&self.source, self.current_index) {
+              Some(name_and_length) => name_and_length,
+              None => return Err(self.error(LexErrorKind::ExpectedIdentifier)),
+            };
+            self.indices_to_skip = length - 1;
+            pending_export.export.named_exports.push(NamedExport {
+              local_name: String::from(name),
+              exported_name: String::from(name),
+            });
+            pending_export.expected_token = ExportToken::NamedExportAs;
           }
         }
+        self.keep_using_handler();
       },
-    }
-  }
-
-  fn handle_export(&mut self, js_module: &mut JavascriptModule) {
-    let mut pending_export = self.pending_export.as_mut().unwrap();
-
-    match pending_export.expected_token {
-      ExportToken::Variables => {
+      ExportToken::NamedExportAs => {
         match self.current_char {
-          '{' => {
-            pending_export.expected_token = ExportToken::NamedExport;
+          c if c.is_whitespace() => {},
+          'a' if identifier_at(&self.source, self.current_index) == "as" => {
+            self.indices_to_skip = 1;
+            pending_export.expected_token = ExportToken::NamedExportAlias;
           },
-          '*' => {
+          '}' => {
             pending_export.expected_token = ExportToken::From;
           },
-          _ => {}
+          ',' => {
+            pending_export.expected_token = ExportToken::NamedExport;
+          },
+          _ => {
+            return Err(self.error(LexErrorKind::ExpectedCommaAsOrBrace));
+          }
         }
-
         self.keep_using_handler();
       },
-      ExportToken::NamedExport => {
-        if pending_export.token_start.is_none() {
-          match self.current_char {
-            c if c.is_whitespace() => {},
-            '\u{0041}'..='\u{2FA1D}' => {
-              pending_export.token_start = Some(self.current_index);
-            },
-            _ => {
-              panic!(format!("Invalid character '{}' at index {} - expected identifier start", self.current_char, self.current_index));
-            }
-          }
-        } else {
-          match self.current_char {
-            '\u{0030}'..='\u{E01EF}' => {},
-            _ => {
-              let identifier = String::from(self.source.get(pending_export.token_start.unwrap()..self.current_index).unwrap());
-              if identifier == "default" {
-                  // TODO: this can be improved.  "default as" w/ backtracking?
-                  // reset since next identifier will alias default "export { default as ... }"
-                  pending_export.token_start = None;
-                  pending_export.expected_token = ExportToken::NextNamedExport;
-              } else {
-                  let named_export = NamedExport {
-                    variable_name: identifier.clone(),
-                    binding_name: identifier,
-                  };
-                  pending_export.export.named_exports.push(named_export);
-                  let next_token;
-                  match self.current_char {
-                    '}' => {
-                      next_token = ExportToken::From;
-                    },
-                    ',' => {
-                      next_token = ExportToken::NamedExport;
-                    },
-                    _ => {
-                      next_token = ExportToken::NextNamedExport;
-                    }
-                  }
-                  pending_export.expected_token = next_token;
-                  pending_export.token_start = None;
-              }
-            }
+      ExportToken::NamedExportAlias => {
+        match self.current_char {
+          c if c.is_whitespace() => {},
+          _ => {
+            let (name, length) = match module_export_name_at(&self.source, self.current_index) {
+              Some(name_and_length) => name_and_length,
+              None => return Err(self.error(LexErrorKind::ExpectedIdentifier)),
+            };
+            self.indices_to_skip = length - 1;
+            let named_export = pending_export.export.named_exports.last_mut().unwrap();
+            named_export.exported_name = String::from(name);
+            pending_export.expected_token = ExportToken::NextNamedExport;
           }
         }
         self.keep_using_handler();
       },
       ExportToken::NextNamedExport => {
         match self.current_char {
+          c if c.is_whitespace() => {},
           '}' => {
             pending_export.expected_token = ExportToken::From;
           },
           ',' => {
             pending_export.expected_token = ExportToken::NamedExport;
           },
-          'a' => {
-            // re-export: `export { default as b } from './b';`
-            self.indices_to_skip = 1;
-            pending_export.expected_token = ExportToken::NamedExport;
-          },
           _ => {
-            panic!(format!("Invalid character '{}' at index {} - expected ',' or keyword 'as' or '}}'", self.current_char, self.current_index));
+            return Err(self.error(LexErrorKind::ExpectedCommaOrBrace));
           }
         }
         self.keep_using_handler();
       },
       ExportToken::From => {
         match self.current_char {
+          // `export { b }` followed by another statement on the next line
+          '\n' if !pending_export.export.star_export && pending_export.export.namespace_export.is_none()
+            && word_after(&self.source, self.current_index) != "from" => {
+            js_module.local_exports.extend(self.pending_export.take().unwrap().export.named_exports);
+            self.queue_handler(Handler::Normal);
+            return Ok(());
+          },
           c if c.is_whitespace() => {},
           'f' => {
             match self.source.get(self.current_index..self.current_index+4).unwrap_or("f") {
@@ -483,20 +1384,18 @@ impl JavascriptLexer {
                 pending_export.expected_token = ExportToken::Specifier;
               },
               _ => {
-                panic!(format!("Invalid character '{}' at index {} - expected keyword 'from'", self.current_char, self.current_index));
+                return Err(self.error(LexErrorKind::ExpectedFrom));
               }
             }
           },
           ';' => {
-            // export { b };
-            // This ended up not being an export with a specifier, so do not record as
-            // pending_export
-            self.pending_export = None;
+            // `export { b };` exports local variables rather than re-exporting from a specifier
+            js_module.local_exports.extend(self.pending_export.take().unwrap().export.named_exports);
             self.queue_handler(Handler::Normal);
-            return;
+            return Ok(());
           }
           _ => {
-            panic!(format!("Invalid character '{}' at index {} - expected keyword 'from' or statement end ';'", self.current_char, self.current_index));
+            return Err(self.error(LexErrorKind::ExpectedFromOrStatementEnd));
           }
         }
         self.keep_using_handler();
@@ -512,13 +1411,13 @@ impl JavascriptLexer {
               pending_export.export.specifier_start = self.current_index + 1;
             },
             _ => {
-              panic!(format!("Invalid character '{}' at index {} - expected string start ' or \"", self.current_char, self.current_index));
+              return Err(self.error(LexErrorKind::ExpectedString));
             }
           }
         } else {
           match self.current_char {
             c if c == pending_export.str_char.unwrap() => {
-              pending_export.export.specifier = String::from(self.source.get(pending_export.token_start.unwrap()..self.current_index).unwrap());
+              pending_export.export.specifier = String::from(self.source.get(pending_export.export.specifier_start..self.current_index).unwrap());
               pending_export.expected_token = ExportToken::StatementEnd;
               pending_export.export.specifier_end = self.current_index - 1;
             },
@@ -529,17 +1428,132 @@ impl JavascriptLexer {
       },
       ExportToken::StatementEnd => {
         match self.current_char {
-          c if c == ' ' => {},
-          ';' | '\n' | '\r' => {
+          ';' => {
+            js_module.exports.push(self.pending_export.take().unwrap().export);
+            self.queue_handler(Handler::Normal);
+            return Ok(());
+          },
+          '\n' if word_after(&self.source, self.current_index) != "with" => {
             js_module.exports.push(self.pending_export.take().unwrap().export);
             self.queue_handler(Handler::Normal);
+            return Ok(());
+          },
+          c if c.is_whitespace() => {},
+          c if is_identifier_start(c) && pending_export.export.attributes.is_empty() => {
+            let (attributes, attributes_end) = self.import_attributes_at_current_index()?;
+            let export = &mut self.pending_export.as_mut().unwrap().export;
+            export.attributes = attributes;
+            export.attributes_start = self.current_index;
+            export.attributes_end = attributes_end;
+          },
+          _ => {
+            return Err(self.error(LexErrorKind::ExpectedStatementEnd));
+          }
+        }
+        self.keep_using_handler();
+      },
+      ExportToken::DeclarationName => {
+        match self.current_char {
+          c if c.is_whitespace() => {},
+          // `export function* gen`
+          '*' => {},
+          c if is_identifier_start(c) => {
+            let name = identifier_at(&self.source, self.current_index);
+            self.indices_to_skip = name.len() - 1;
+            // `export async function f`
+            if name != "function" {
+              js_module.local_exports.push(NamedExport {
+                local_name: String::from(name),
+                exported_name: String::from(name),
+              });
+              // the body is left to handle_normal
+              self.pending_export = None;
+              self.queue_handler(Handler::Normal);
+              return Ok(());
+            }
+          },
+          _ => {
+            return Err(self.error(LexErrorKind::ExpectedIdentifier));
+          }
+        }
+        self.keep_using_handler();
+      },
+      ExportToken::DeclarationBinding => {
+        match self.current_char {
+          c if c.is_whitespace() => {},
+          '{' | '[' => {
+            // `export const { a, b: c } = object;`
+            let mut names = Vec::new();
+            let pattern_end = match binding_pattern_at(&self.source, self.current_index, &mut names) {
+              Some(pattern_end) => pattern_end,
+              None => return Err(self.error(LexErrorKind::ExpectedIdentifier)),
+            };
+            self.indices_to_skip = self.source[self.current_index..pattern_end].chars().count() - 1;
+            js_module.local_exports.extend(names.into_iter().map(|name| NamedExport {
+              local_name: name.clone(),
+              exported_name: name,
+            }));
+            pending_export.expected_token = ExportToken::DeclarationInitializer;
+          },
+          c if is_identifier_start(c) => {
+            let name = identifier_at(&self.source, self.current_index);
+            self.indices_to_skip = name.len() - 1;
+            js_module.local_exports.push(NamedExport {
+              local_name: String::from(name),
+              exported_name: String::from(name),
+            });
+            pending_export.expected_token = ExportToken::DeclarationInitializer;
           },
           _ => {
-            panic!(format!("Invalid character '{}' at index {} - expected statement end", self.current_char, self.current_index));
+            return Err(self.error(LexErrorKind::ExpectedIdentifier));
           }
         }
+        self.keep_using_handler();
+      },
+      ExportToken::DeclarationInitializer => {
+        let at_top_level = pending_export.declaration_depth == 0;
+        match self.current_char {
+          ',' if at_top_level => {
+            pending_export.expected_token = ExportToken::DeclarationBinding;
+          },
+          ';' if at_top_level => {
+            self.pending_export = None;
+            self.queue_handler(Handler::Normal);
+            return Ok(());
+          },
+          '\n' if at_top_level => {
+            // a line comment before the line break is not part of the expression
+            let code_end = pending_export.token_start.take().unwrap_or(self.current_index);
+            if !continues_expression(&self.source, code_end, self.current_index) {
+              self.pending_export = None;
+              self.queue_handler(Handler::Normal);
+              return Ok(());
+            }
+          },
+          '/' if self.source[self.current_index..].starts_with("//") => {
+            pending_export.token_start = Some(self.current_index);
+            self.keep_using_handler();
+            self.queue_literal_or_comment_handler();
+            return Ok(());
+          },
+          '(' | '[' | '{' => {
+            pending_export.declaration_depth += 1;
+          },
+          ')' | ']' | '}' if !at_top_level => {
+            pending_export.declaration_depth -= 1;
+          },
+          '/' | '\'' | '"' | '`' => {
+            self.keep_using_handler();
+            self.queue_literal_or_comment_handler();
+            return Ok(());
+          },
+          _ => {}
+        }
+        self.keep_using_handler();
       },
     }
+
+    Ok(())
   }
 }
 
@@ -552,7 +1566,7 @@ mod tests {
         let source = String::from("
 import A from './a.js';
 ");
-        let module = JavascriptLexer::new(source.clone()).parse_module();
+        let module = JavascriptLexer::new(source.clone()).parse_module().unwrap();
         assert_eq!(module.imports.len(), 1);
         assert_eq!(module.exports.len(), 0);
         assert_eq!(module.raw_source, source);
@@ -563,34 +1577,39 @@ import A from './a.js';
 import A from './a.js';
 import c from './c.js';
 ");
-        let module = JavascriptLexer::new(source.clone()).parse_module();
+        let module = JavascriptLexer::new(source.clone()).parse_module().unwrap();
         assert_eq!(module.imports.len(), 2);
         assert_eq!(module.exports.len(), 0);
         assert_eq!(module.raw_source, source);
     }
 
     #[test]
-    fn does_not_parse_default_export() {
+    fn parses_local_default_export() {
         let source = String::from("
 class C {}
 export default C;
 ");
-        let module = JavascriptLexer::new(source.clone()).parse_module();
+        let module = JavascriptLexer::new(source.clone()).parse_module().unwrap();
         assert_eq!(module.imports.len(), 0);
         assert_eq!(module.exports.len(), 0);
+        assert_eq!(module.exported_names(), vec!["default"]);
         assert_eq!(module.raw_source, source);
     }
 
     #[test]
-    fn does_not_parse_named_export() {
+    fn parses_local_named_export() {
         let source = String::from("
 const e = 'e';
 
 export { e };
 ");
-        let module = JavascriptLexer::new(source.clone()).parse_module();
+        let module = JavascriptLexer::new(source.clone()).parse_module().unwrap();
         assert_eq!(module.imports.len(), 0);
         assert_eq!(module.exports.len(), 0);
+        assert_eq!(module.local_exports, vec![NamedExport {
+            local_name: String::from("e"),
+            exported_name: String::from("e")
+        }]);
         assert_eq!(module.raw_source, source);
     }
 
@@ -599,7 +1618,7 @@ export { e };
         let source = String::from("
 export { b } from './b.js';
 ");
-        let module = JavascriptLexer::new(source.clone()).parse_module();
+        let module = JavascriptLexer::new(source.clone()).parse_module().unwrap();
         assert_eq!(module.imports.len(), 0);
         assert_eq!(module.exports.len(), 1);
         assert_eq!(module.raw_source, source);
@@ -610,7 +1629,7 @@ export { b } from './b.js';
         let source = String::from("
 export { b as default } from './b.js';
 ");
-        let module = JavascriptLexer::new(source.clone()).parse_module();
+        let module = JavascriptLexer::new(source.clone()).parse_module().unwrap();
         assert_eq!(module.imports.len(), 0);
         assert_eq!(module.exports.len(), 1);
         assert_eq!(module.raw_source, source);
@@ -621,7 +1640,7 @@ export { b as default } from './b.js';
         let source = String::from("
 export * from './b.js';
 ");
-        let module = JavascriptLexer::new(source.clone()).parse_module();
+        let module = JavascriptLexer::new(source.clone()).parse_module().unwrap();
         assert_eq!(module.imports.len(), 0);
         assert_eq!(module.exports.len(), 1);
         assert_eq!(module.raw_source, source);
@@ -635,7 +1654,7 @@ import cDefault from './c.js';
 export * from './d.js';
 export { b as default } from './b.js';
 ");
-        let module = JavascriptLexer::new(source.clone()).parse_module();
+        let module = JavascriptLexer::new(source.clone()).parse_module().unwrap();
         assert_eq!(module.imports.len(), 2);
         assert_eq!(module.imports[0].specifier, "./a.js");
         assert_eq!(module.imports[0].named_imports.len(), 1);
@@ -644,6 +1663,7 @@ export { b as default } from './b.js';
             binding_name: String::from("AImport")
         }]);
         assert_eq!(module.imports[0].default_import, None);
+        assert_eq!(module.imports[0].namespace_import, None);
         assert_eq!(module.imports[1].specifier, "./c.js");
         assert_eq!(module.imports[1].named_imports.len(), 0);
         assert_eq!(module.imports[1].default_import, Some(DefaultImport {
@@ -652,7 +1672,430 @@ export { b as default } from './b.js';
         }));
         assert_eq!(module.exports.len(), 2);
         assert_eq!(module.exports[0].specifier, "./d.js");
+        assert!(module.exports[0].star_export);
         assert_eq!(module.exports[1].specifier, "./b.js");
+        assert_eq!(module.exports[1].named_exports, vec![NamedExport {
+            local_name: String::from("b"),
+            exported_name: String::from("default")
+        }]);
         assert_eq!(module.raw_source, source);
     }
+
+    #[test]
+    fn parses_import_at_end_of_input() {
+        let source = String::from("import A from './a.js'");
+        let module = JavascriptLexer::new(source).parse_module().unwrap();
+        assert_eq!(module.imports.len(), 1);
+        assert_eq!(module.imports[0].specifier, "./a.js");
+    }
+
+    #[test]
+    fn reports_invalid_character_with_position() {
+        let source = String::from("
+import A from './a.js';
+import B fom './b.js';
+");
+        let err = JavascriptLexer::new(source).with_file_path(String::from("src/main.js")).parse_module().unwrap_err();
+        assert_eq!(err.kind, LexErrorKind::ExpectedFrom);
+        assert_eq!(err.found, Some('f'));
+        assert_eq!(err.line, 3);
+        assert_eq!(err.column, 10);
+        assert_eq!(err.file_path, Some(String::from("src/main.js")));
+        assert_eq!(err.code_frame, "  |\n3 | import B fom './b.js';\n  |          ^");
+        assert_eq!(err.to_string(), "src/main.js:3:10: expected keyword 'from', found 'f'\n  |\n3 | import B fom './b.js';\n  |          ^");
+    }
+
+    #[test]
+    fn reports_unexpected_end_of_input() {
+        let source = String::from("import { A, B");
+        let err = JavascriptLexer::new(source).parse_module().unwrap_err();
+        assert_eq!(err.kind, LexErrorKind::UnexpectedEndOfInput);
+        assert_eq!(err.found, None);
+        assert_eq!(err.line, 1);
+        assert_eq!(err.column, 14);
+    }
+
+    #[test]
+    fn ignores_imports_and_exports_in_comments() {
+        let source = String::from("
+// import A from './a.js';
+/* export * from './b.js';
+   import { C } from './c.js'; */
+import D from './d.js'; // export { e } from './e.js';
+");
+        let module = JavascriptLexer::new(source).parse_module().unwrap();
+        assert_eq!(module.imports.len(), 1);
+        assert_eq!(module.imports[0].specifier, "./d.js");
+        assert_eq!(module.exports.len(), 0);
+    }
+
+    #[test]
+    fn ignores_imports_and_exports_in_strings_and_templates() {
+        let source = String::from("
+const a = \"import A from './a.js';\";
+const b = 'export * from \\'./b.js\\';';
+const c = `import C from './c.js'; ${ { nested: `export * from './d.js'` }.nested } export { e } from './e.js'`;
+import F from './f.js';
+");
+        let module = JavascriptLexer::new(source).parse_module().unwrap();
+        assert_eq!(module.imports.len(), 1);
+        assert_eq!(module.imports[0].specifier, "./f.js");
+        assert_eq!(module.exports.len(), 0);
+    }
+
+    #[test]
+    fn ignores_imports_in_regular_expressions() {
+        let source = String::from("
+const re = /import A from '[./]a.js'/g;
+const ratio = width / 2 / height;
+const escaped = /\\/ export * from '.\\/b.js'/;
+import C from './c.js';
+");
+        let module = JavascriptLexer::new(source).parse_module().unwrap();
+        assert_eq!(module.imports.len(), 1);
+        assert_eq!(module.imports[0].specifier, "./c.js");
+        assert_eq!(module.exports.len(), 0);
+    }
+
+    #[test]
+    fn requires_identifier_boundaries_around_keywords() {
+        let source = String::from("
+const reimport = 1;
+const exporter = { import: reimport };
+module.exports = exporter.import;
+const lazy = import('./lazy.js');
+console.log(import.meta.url);
+import A from './a.js';
+");
+        let module = JavascriptLexer::new(source).parse_module().unwrap();
+        assert_eq!(module.imports.len(), 1);
+        assert_eq!(module.imports[0].specifier, "./a.js");
+        assert_eq!(module.exports.len(), 0);
+    }
+
+    #[test]
+    fn does_not_parse_export_declarations_as_re_exports() {
+        let source = String::from("
+export function f() { return '{'; }
+export const o = { a: 1 };
+export { b } from './b.js';
+");
+        let module = JavascriptLexer::new(source).parse_module().unwrap();
+        assert_eq!(module.imports.len(), 0);
+        assert_eq!(module.exports.len(), 1);
+        assert_eq!(module.exports[0].specifier, "./b.js");
+    }
+
+    #[test]
+    fn parses_side_effect_import() {
+        let source = String::from("
+import './polyfill.js';
+import \"./register.css.js\"
+import A from './a.js';
+");
+        let module = JavascriptLexer::new(source.clone()).parse_module().unwrap();
+        assert_eq!(module.imports.len(), 3);
+        assert_eq!(module.imports[0], JavascriptImport {
+            default_name: None,
+            default_import: None,
+            namespace_import: None,
+            named_imports: Vec::new(),
+            specifier: String::from("./polyfill.js"),
+            specifier_start: 9,
+            specifier_end: 21,
+            attributes: Vec::new(),
+            attributes_start: 0,
+            attributes_end: 0,
+            module_id: None,
+        });
+        assert_eq!(&source[module.imports[1].specifier_start..=module.imports[1].specifier_end], "./register.css.js");
+        assert_eq!(module.imports[1].named_imports.len(), 0);
+        assert_eq!(module.imports[1].default_import, None);
+        assert_eq!(module.imports[2].specifier, "./a.js");
+    }
+
+    #[test]
+    fn parses_namespace_import() {
+        let source = String::from("
+import * as utils from './utils.js';
+import A, *   as\tB from './b.js';
+");
+        let module = JavascriptLexer::new(source).parse_module().unwrap();
+        assert_eq!(module.imports.len(), 2);
+        assert_eq!(module.imports[0].namespace_import, Some(String::from("utils")));
+        assert_eq!(module.imports[0].default_import, None);
+        assert_eq!(module.imports[0].specifier, "./utils.js");
+        assert_eq!(module.imports[1].namespace_import, Some(String::from("B")));
+        assert_eq!(module.imports[1].default_import, Some(DefaultImport {
+            variable_name: String::from("A"),
+            binding_name: String::from("A")
+        }));
+        assert_eq!(module.imports[1].specifier, "./b.js");
+    }
+
+    #[test]
+    fn reports_namespace_import_without_as() {
+        let source = String::from("import * from './utils.js';");
+        let err = JavascriptLexer::new(source).parse_module().unwrap_err();
+        assert_eq!(err.kind, LexErrorKind::ExpectedAs);
+        assert_eq!(err.column, 10);
+    }
+
+    #[test]
+    fn parses_aliased_named_imports() {
+        let source = String::from("
+import { a as b, default as C, \"a-b\" as ab, d, } from './a.js';
+import {} from './b.js';
+");
+        let module = JavascriptLexer::new(source).parse_module().unwrap();
+        assert_eq!(module.imports.len(), 2);
+        assert_eq!(module.imports[0].named_imports, vec![
+            NamedImport {
+                variable_name: String::from("b"),
+                binding_name: String::from("a")
+            },
+            NamedImport {
+                variable_name: String::from("C"),
+                binding_name: String::from("default")
+            },
+            NamedImport {
+                variable_name: String::from("ab"),
+                binding_name: String::from("a-b")
+            },
+            NamedImport {
+                variable_name: String::from("d"),
+                binding_name: String::from("d")
+            },
+        ]);
+        assert_eq!(module.imports[0].specifier, "./a.js");
+        assert_eq!(module.imports[1].named_imports.len(), 0);
+        assert_eq!(module.imports[1].specifier, "./b.js");
+    }
+
+    #[test]
+    fn reports_string_import_name_without_alias() {
+        let source = String::from("import { \"a-b\" } from './a.js';");
+        let err = JavascriptLexer::new(source).parse_module().unwrap_err();
+        assert_eq!(err.kind, LexErrorKind::ExpectedAs);
+        assert_eq!(err.column, 16);
+    }
+
+    #[test]
+    fn parses_dynamic_imports() {
+        let source = String::from("
+const lazy = import('./lazy.js');
+const other = import ( \"./other.js\", { with: { type: 'javascript' } } );
+const computed = import(`./routes/${name}.js`);
+const concatenated = import('./a' + '.js');
+");
+        let module = JavascriptLexer::new(source.clone()).parse_module().unwrap();
+        assert_eq!(module.imports.len(), 0);
+        assert_eq!(module.dynamic_imports.len(), 2);
+        assert_eq!(module.dynamic_imports[0].specifier, "./lazy.js");
+        assert_eq!(&source[module.dynamic_imports[0].specifier_start..=module.dynamic_imports[0].specifier_end], "./lazy.js");
+        assert_eq!(module.dynamic_imports[1].specifier, "./other.js");
+        assert_eq!(&source[module.dynamic_imports[1].specifier_start..=module.dynamic_imports[1].specifier_end], "./other.js");
+        assert_eq!(module.dynamic_imports[1].attributes, vec![ImportAttribute { key: String::from("type"), value: String::from("javascript") }]);
+        assert_eq!(&source[module.dynamic_imports[1].options_start..=module.dynamic_imports[1].options_end], ", { with: { type: 'javascript' } }");
+        assert!(module.dynamic_imports[0].attributes.is_empty());
+    }
+
+    #[test]
+    fn parses_re_exports() {
+        let source = String::from("
+export * from './a.js';
+export * as ns from './b.js';
+export * as \"some-ns\" from './c.js';
+export { d, e as f, default, default as g, h as default, \"i-j\" as k, l as \"m-n\", } from './d.js';
+export {} from './e.js';
+");
+        let module = JavascriptLexer::new(source).parse_module().unwrap();
+        assert_eq!(module.exports.len(), 5);
+        assert!(module.exports[0].star_export);
+        assert_eq!(module.exports[0].namespace_export, None);
+        assert_eq!(module.exports[0].specifier, "./a.js");
+        assert!(!module.exports[1].star_export);
+        assert_eq!(module.exports[1].namespace_export, Some(String::from("ns")));
+        assert_eq!(module.exports[1].specifier, "./b.js");
+        assert_eq!(module.exports[2].namespace_export, Some(String::from("some-ns")));
+        assert_eq!(module.exports[2].specifier, "./c.js");
+        let names: Vec<(&str, &str)> = module.exports[3].named_exports.iter()
+            .map(|named_export| (named_export.local_name.as_str(), named_export.exported_name.as_str()))
+            .collect();
+        assert_eq!(names, vec![
+            ("d", "d"),
+            ("e", "f"),
+            ("default", "default"),
+            ("default", "g"),
+            ("h", "default"),
+            ("i-j", "k"),
+            ("l", "m-n"),
+        ]);
+        assert!(!module.exports[3].star_export);
+        assert_eq!(module.exports[3].specifier, "./d.js");
+        assert_eq!(module.exports[4].named_exports.len(), 0);
+        assert_eq!(module.exports[4].specifier, "./e.js");
+    }
+
+    #[test]
+    fn parses_local_exports() {
+        let source = String::from("
+export const a = 1, b = { c: [1, 2] }, d = f(1, 2);
+export let { e, f: g, h = { i: 1 }, ...j } = object
+export var [k, , l = [1, 2], ...m] = array;
+export const n = `${a}`, o = '/', p = /,/, q = a
+  + b // a comment, with a comma
+export function r() { return 1; }
+export async function s() {}
+export function* t() {}
+export class U {}
+export { a as v, b, \"w-x\" as \"y-z\" };
+export default function () {}
+export { a as aa } from './a.js';
+export * as ns from './b.js';
+export * from './c.js';
+");
+        let module = JavascriptLexer::new(source).parse_module().unwrap();
+        let names: Vec<(&str, &str)> = module.local_exports.iter()
+            .map(|named_export| (named_export.local_name.as_str(), named_export.exported_name.as_str()))
+            .collect();
+        assert_eq!(names, vec![
+            ("a", "a"), ("b", "b"), ("d", "d"),
+            ("e", "e"), ("g", "g"), ("h", "h"), ("j", "j"),
+            ("k", "k"), ("l", "l"), ("m", "m"),
+            ("n", "n"), ("o", "o"), ("p", "p"), ("q", "q"),
+            ("r", "r"), ("s", "s"), ("t", "t"), ("U", "U"),
+            ("a", "v"), ("b", "b"), ("w-x", "y-z"),
+            ("default", "default"),
+        ]);
+        assert_eq!(module.exports.len(), 3);
+        let exported_names = module.exported_names();
+        assert_eq!(&exported_names[exported_names.len() - 2..], ["aa", "ns"]);
+    }
+
+    #[test]
+    fn reports_unknown_export_declaration() {
+        let source = String::from("export foo;");
+        let err = JavascriptLexer::new(source).parse_module().unwrap_err();
+        assert_eq!(err.kind, LexErrorKind::ExpectedDeclaration);
+        assert_eq!(err.column, 8);
+    }
+
+    #[test]
+    fn parses_statements_across_lines_without_semicolons() {
+        let source = String::from("
+import {
+  a,
+  b as c, // trailing comment
+} from './a.js'
+import D /* default */, * as E from './d.js'	;
+import F
+  from
+  './f.js' // trailing comment
+import './g.js' /* spans
+lines */ run()
+export {
+  h,
+} from './h.js'
+export * from './i.js' // comment
+export { j }
+export const k = 1 // a comment, with a comma
+  + 2, l = 3
+export { m }
+  from './m.js'
+const n = 1
+");
+        let module = JavascriptLexer::new(source).parse_module().unwrap();
+        let specifiers: Vec<&str> = module.imports.iter().map(|import| import.specifier.as_str()).collect();
+        assert_eq!(specifiers, vec!["./a.js", "./d.js", "./f.js", "./g.js"]);
+        assert_eq!(module.imports[0].named_imports.len(), 2);
+        assert_eq!(module.imports[1].default_import, Some(DefaultImport {
+            variable_name: String::from("D"),
+            binding_name: String::from("D")
+        }));
+        assert_eq!(module.imports[1].namespace_import, Some(String::from("E")));
+        assert_eq!(module.imports[2].default_import.as_ref().unwrap().variable_name, "F");
+        let specifiers: Vec<&str> = module.exports.iter().map(|export| export.specifier.as_str()).collect();
+        assert_eq!(specifiers, vec!["./h.js", "./i.js", "./m.js"]);
+        let local_names: Vec<&str> = module.local_exports.iter().map(|named_export| named_export.exported_name.as_str()).collect();
+        assert_eq!(local_names, vec!["j", "k", "l"]);
+    }
+
+    #[test]
+    fn reports_code_after_import_on_the_same_line() {
+        let source = String::from("import A from './a.js' run();");
+        let err = JavascriptLexer::new(source).parse_module().unwrap_err();
+        assert_eq!(err.kind, LexErrorKind::ExpectedStatementEnd);
+        assert_eq!(err.column, 24);
+    }
+
+    #[test]
+    fn parses_import_attributes() {
+        let source = String::from("
+import data from './data.json' with { type: 'json' };
+import legacy from './legacy.json' assert { \"type\": \"json\", other: 'x', }
+import wrapped from './wrapped.json'
+  with { type: 'json' }
+export { default as config } from './config.json' with { type: 'json' }
+import plain from './plain.js'
+with_statement();
+");
+        let module = JavascriptLexer::new(source.clone()).parse_module().unwrap();
+        assert_eq!(module.imports.len(), 4);
+        assert_eq!(module.imports[0].attributes, vec![ImportAttribute {
+            key: String::from("type"),
+            value: String::from("json")
+        }]);
+        let attributes: Vec<(&str, &str)> = module.imports[1].attributes.iter()
+            .map(|attribute| (attribute.key.as_str(), attribute.value.as_str()))
+            .collect();
+        assert_eq!(attributes, vec![("type", "json"), ("other", "x")]);
+        assert_eq!(&source[module.imports[0].attributes_start..=module.imports[0].attributes_end], "with { type: 'json' }");
+        assert_eq!(module.imports[2].specifier, "./wrapped.json");
+        assert_eq!(module.imports[2].attributes.len(), 1);
+        assert_eq!(module.imports[3].attributes.len(), 0);
+        assert_eq!(module.exports[0].specifier, "./config.json");
+        assert_eq!(module.exports[0].attributes.len(), 1);
+    }
+
+    #[test]
+    fn reports_invalid_import_attributes() {
+        let source = String::from("import data from './data.json' with { type: json };");
+        let err = JavascriptLexer::new(source).parse_module().unwrap_err();
+        assert_eq!(err.kind, LexErrorKind::ExpectedImportAttributes);
+        assert_eq!(err.column, 32);
+    }
+
+    #[test]
+    fn parses_asset_references() {
+        let source = String::from("
+const logo = new URL('./logo.png', import.meta.url);
+const worker = new Worker(new URL( \"../worker.js\" , import.meta.url ));
+const remote = new URL('https://example.com/a.png', import.meta.url);
+const relative = new URL('./a.png', location.href);
+const renew = 1;
+");
+        let module = JavascriptLexer::new(source.clone()).parse_module().unwrap();
+        assert_eq!(module.asset_references.len(), 2);
+        assert_eq!(module.asset_references[0].specifier, "./logo.png");
+        assert_eq!(&source[module.asset_references[0].specifier_start..=module.asset_references[0].specifier_end], "./logo.png");
+        assert_eq!(module.asset_references[1].specifier, "../worker.js");
+        assert_eq!(module.dynamic_imports.len(), 0);
+    }
+
+    #[test]
+    fn parses_references_in_exported_initializers() {
+        let source = String::from("
+export const route = () => import('./lazy.js');
+export const logo = new URL('./logo.png', import.meta.url);
+export const routes = { b: import('./b.js') }, page = lazy(() => import(\"./page.js\"));
+");
+        let module = JavascriptLexer::new(source.clone()).parse_module().unwrap();
+        let specifiers: Vec<&str> = module.dynamic_imports.iter().map(|dynamic_import| dynamic_import.specifier.as_str()).collect();
+        assert_eq!(specifiers, vec!["./lazy.js", "./b.js", "./page.js"]);
+        assert_eq!(&source[module.dynamic_imports[2].specifier_start..=module.dynamic_imports[2].specifier_end], "./page.js");
+        assert_eq!(module.asset_references.len(), 1);
+        assert_eq!(module.asset_references[0].specifier, "./logo.png");
+        assert_eq!(module.exported_names(), vec!["route", "logo", "routes", "page"]);
+    }
 }
@@ -0,0 +1,344 @@
+use super::lexer;
+use super::resolver;
+use std::fmt;
+
+// How a file in the module graph is turned into a JavascriptModule
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Loader {
+  Javascript,
+  Json,
+  Css,
+  // copied into the output directory, with the module exporting its URL
+  Asset,
+}
+
+// Files that are emitted as they are rather than loaded as modules
+const ASSET_EXTENSIONS: [&str; 20] = [
+  "png", "jpg", "jpeg", "gif", "svg", "webp", "avif", "ico", "bmp",
+  "woff", "woff2", "ttf", "otf", "eot",
+  "wasm", "mp3", "mp4", "webm", "ogg", "wav",
+];
+
+#[derive(Debug)]
+pub enum LoadError {
+  InvalidJson(serde_json::Error),
+  // the module generated from a stylesheet would not lex
+  InvalidCss(lexer::LexError),
+}
+
+impl fmt::Display for LoadError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      LoadError::InvalidJson(err) => write!(f, "invalid JSON: {}", err),
+      LoadError::InvalidCss(err) => write!(f, "invalid CSS: {}", err),
+    }
+  }
+}
+
+impl Loader {
+  // An import's `type` attribute decides the loader, falling back to the file extension
+  pub fn for_import(file_path: &str, attributes: &[lexer::ImportAttribute]) -> Loader {
+    match attributes.iter().find(|attribute| attribute.key == "type") {
+      Some(attribute) if attribute.value == "json" => Loader::Json,
+      Some(attribute) if attribute.value == "css" => Loader::Css,
+      _ if file_path.ends_with(".json") => Loader::Json,
+      _ if file_path.ends_with(".css") => Loader::Css,
+      _ if file_path.rsplit_once('.').is_some_and(|(_, extension)| ASSET_EXTENSIONS.contains(&extension.to_lowercase().as_str())) => Loader::Asset,
+      _ => Loader::Javascript,
+    }
+  }
+}
+
+// A JSON file becomes a module whose default export is the parsed value. The JSON is
+// parsed at runtime rather than pasted in as an object literal, where a "__proto__" key
+// would set the prototype instead of being an own property.
+pub fn load_json(source: String) -> Result<lexer::JavascriptModule, LoadError> {
+  let json = source.trim_start_matches('\u{FEFF}').trim();
+  serde_json::from_str::<serde_json::Value>(json).map_err(LoadError::InvalidJson)?;
+
+  Ok(lexer::JavascriptModule {
+    imports: Vec::new(),
+    exports: Vec::new(),
+    local_exports: vec![lexer::NamedExport {
+      local_name: String::from("default"),
+      exported_name: String::from("default"),
+    }],
+    dynamic_imports: Vec::new(),
+    asset_references: Vec::new(),
+    raw_source: format!("export default JSON.parse({});\n", serde_json::to_string(json).unwrap()),
+  })
+}
+
+// A CSS file becomes a module whose default export is a constructable stylesheet.
+// replaceSync() ignores @import rules, so each one is imported as a module of its own
+// and its rules are copied in ahead of the file's own. The sheet resolves url()s against
+// the document rather than the file, so relative ones become assets of the module.
+pub fn load_css(source: String) -> Result<lexer::JavascriptModule, LoadError> {
+  let (css_imports, css) = split_css_imports(source.trim_start_matches('\u{FEFF}'));
+
+  let mut js = String::new();
+  let mut rules = Vec::new();
+  for (index, (url, media)) in css_imports.iter().enumerate() {
+    js.push_str(&format!("import dependency{} from {} with {{ type: 'css' }};\n", index, serde_json::to_string(url).unwrap()));
+    let dependency_rules = format!("rules(dependency{})", index);
+    rules.push(match media {
+      Some(media) => format!("{} + {} + \"}}\"", serde_json::to_string(&format!("@media {} {{", media)).unwrap(), dependency_rules),
+      None => dependency_rules,
+    });
+  }
+  rules.push(css_rules(css));
+
+  if !css_imports.is_empty() {
+    js.push_str("const rules = (sheet) => Array.from(sheet.cssRules, (rule) => rule.cssText).join(\"\\n\");\n");
+  }
+  js.push_str("const sheet = new CSSStyleSheet();\n");
+  js.push_str(&format!("sheet.replaceSync({});\n", rules.join(" + ")));
+  js.push_str("export default sheet;\n");
+
+  lexer::JavascriptLexer::new(js).parse_module().map_err(LoadError::InvalidCss)
+}
+
+// An asset's module refers to the asset file itself, so that the bundler rewrites it
+// to the emitted file like any other `new URL(..., import.meta.url)`
+pub fn load_asset(file_path: &str) -> lexer::JavascriptModule {
+  let file_name = file_path.rsplit('/').next().unwrap_or(file_path);
+  let js = format!("export default new URL({}, import.meta.url).href;\n", serde_json::to_string(&format!("./{}", file_name)).unwrap());
+  lexer::JavascriptLexer::new(js).parse_module().unwrap_or_else(|err| panic!("generated asset module is not valid JavaScript: {}", err))
+}
+
+// `logo-1a2b3c4d5e6f7a8b.png`, named after the contents so that a changed file is never
+// cached. All 64 bits are kept, so that two assets with the same name never overwrite each other.
+pub fn asset_file_name(file_path: &str, contents: &[u8]) -> String {
+  let hash = hash(contents);
+  let file_name = file_path.rsplit('/').next().unwrap_or(file_path);
+  match file_name.rsplit_once('.') {
+    Some((stem, extension)) => format!("{}-{:016x}.{}", stem, hash, extension),
+    None => format!("{}-{:016x}", file_name, hash),
+  }
+}
+
+// 64-bit FNV-1a, which is stable across builds and platforms, unlike std's hasher
+pub fn hash(contents: &[u8]) -> u64 {
+  contents.iter().fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3))
+}
+
+// The stylesheet as a JavaScript expression, with each relative url() replaced by the URL
+// of the file it points at, which the bundler emits like any other asset
+fn css_rules(css: &str) -> String {
+  let mut parts = Vec::new();
+  let mut rest = css;
+  while let Some((url_start, url_end, url)) = next_css_url(rest) {
+    if url_start > 0 {
+      parts.push(serde_json::to_string(&rest[..url_start]).unwrap());
+    }
+    // `bg.png` is relative in CSS but would not be an asset reference in JavaScript
+    let (path, suffix) = url.split_at(url.find(['?', '#']).unwrap_or(url.len()));
+    let path = if path.starts_with("./") || path.starts_with("../") { String::from(path) } else { format!("./{}", path) };
+    let suffix = if suffix.is_empty() { String::new() } else { format!(" + {}", serde_json::to_string(suffix).unwrap()) };
+    parts.push(format!("\"url(\" + JSON.stringify(new URL({}, import.meta.url).href{}) + \")\"", serde_json::to_string(&path).unwrap(), suffix));
+    rest = &rest[url_end..];
+  }
+  if parts.is_empty() || !rest.is_empty() {
+    parts.push(serde_json::to_string(rest).unwrap());
+  }
+  parts.join(" + ")
+}
+
+// The next relative url() in the stylesheet, as the index it starts at, the index after
+// its closing parenthesis and the URL. Comments and strings are skipped.
+fn next_css_url(css: &str) -> Option<(usize, usize, &str)> {
+  let mut index = 0;
+  while let Some(c) = css[index..].chars().next() {
+    let rest = &css[index..];
+    if rest.starts_with("/*") {
+      index += rest.find("*/").map_or(rest.len(), |comment_end| comment_end + 2);
+    } else if c == '\'' || c == '"' {
+      index += css_string_length(rest);
+    } else if rest.starts_with("url(") && !css[..index].ends_with(|c: char| c.is_alphanumeric() || c == '-' || c == '_') {
+      match css_url_at(rest) {
+        Some((url_end, url)) if is_relative_css_url(url) => return Some((index, index + url_end, url)),
+        Some((url_end, _)) => index += url_end,
+        None => index += "url(".len(),
+      }
+    } else {
+      index += c.len_utf8();
+    }
+  }
+  None
+}
+
+// The length of the quoted string at the start of css, quotes included
+fn css_string_length(css: &str) -> usize {
+  let mut chars = css.char_indices();
+  let quote = chars.next().map(|(_, quote)| quote);
+  while let Some((index, c)) = chars.next() {
+    if c == '\\' {
+      chars.next();
+    } else if Some(c) == quote || c == '\n' {
+      return index + 1;
+    }
+  }
+  css.len()
+}
+
+// `url(./a.png)` or `url( "./a.png" )` at the start of css, as the index after its
+// closing parenthesis and the URL
+fn css_url_at(css: &str) -> Option<(usize, &str)> {
+  let value_start = css.len() - css["url(".len()..].trim_start().len();
+  let value = &css[value_start..];
+  let (url, value_end) = match value.chars().next()? {
+    quote @ ('\'' | '"') => {
+      let url_end = value[1..].find(quote)? + 1;
+      (&value[1..url_end], value_start + url_end + 1)
+    },
+    _ => {
+      let url_end = value.find(')')?;
+      (value[..url_end].trim_end(), value_start + url_end)
+    },
+  };
+  let close_paren = css.len() - css[value_end..].trim_start().len();
+  if css[close_paren..].starts_with(')') { Some((close_paren + 1, url)) } else { None }
+}
+
+// Root-relative URLs, fragments, and URLs with a scheme like `data:` already mean the same
+// thing in a stylesheet as in the file. Escaped URLs are left as they are too.
+fn is_relative_css_url(url: &str) -> bool {
+  !url.is_empty() && !url.starts_with('/') && !url.starts_with('#') && !resolver::is_url(url)
+    && !url.contains(|c: char| c == '\\' || c == '\'' || c == '"' || c.is_whitespace())
+}
+
+// Separates the leading @import rules, as (url, media) pairs, from the rest of the
+// stylesheet. Imports of remote URLs are left in the stylesheet.
+fn split_css_imports(css: &str) -> (Vec<(String, Option<String>)>, &str) {
+  let mut css_imports = Vec::new();
+  let mut rest = css;
+  loop {
+    let rule = skip_css_comments(rest);
+    let rule_end = match rule.find(';') {
+      Some(rule_end) if rule.starts_with("@charset") => rule_end,
+      Some(rule_end) if rule.starts_with("@import") => rule_end,
+      _ => return (css_imports, rest),
+    };
+    if rule.starts_with("@import") {
+      match parse_css_import(&rule["@import".len()..rule_end]) {
+        Some((url, _)) if url.contains("://") || url.starts_with("//") => return (css_imports, rest),
+        Some(css_import) => css_imports.push(css_import),
+        None => return (css_imports, rest),
+      }
+    }
+    rest = &rule[rule_end + 1..];
+  }
+}
+
+fn skip_css_comments(css: &str) -> &str {
+  let mut css = css.trim_start();
+  while css.starts_with("/*") {
+    css = match css.find("*/") {
+      Some(comment_end) => css[comment_end + 2..].trim_start(),
+      None => "",
+    };
+  }
+  css
+}
+
+// `url("./a.css") screen` or `'./a.css'`, after the @import keyword
+fn parse_css_import(rule: &str) -> Option<(String, Option<String>)> {
+  let rule = rule.trim();
+  let (url, media) = if let Some(url_start) = rule.strip_prefix("url(") {
+    let url_end = url_start.find(')')?;
+    (url_start[..url_end].trim().trim_matches(['\'', '"']), &url_start[url_end + 1..])
+  } else {
+    let quote = rule.chars().next().filter(|&c| c == '\'' || c == '"')?;
+    let url_end = rule[1..].find(quote)?;
+    (&rule[1..url_end + 1], &rule[url_end + 2..])
+  };
+
+  // `@import 'a.css'` is relative in CSS but would be a bare specifier in JavaScript
+  let url = if url.starts_with("./") || url.starts_with("../") || url.starts_with('/') || url.contains("://") {
+    String::from(url)
+  } else {
+    format!("./{}", url)
+  };
+  let media = Some(media.trim()).filter(|media| !media.is_empty()).map(String::from);
+  Some((url, media))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chooses_loader_from_type_attribute_or_extension() {
+        let json_type = vec![lexer::ImportAttribute {
+            key: String::from("type"),
+            value: String::from("json")
+        }];
+        assert_eq!(Loader::for_import("src/config.json", &[]), Loader::Json);
+        assert_eq!(Loader::for_import("src/config.data", &json_type), Loader::Json);
+        assert_eq!(Loader::for_import("src/button.css", &[]), Loader::Css);
+        assert_eq!(Loader::for_import("src/logo.PNG", &[]), Loader::Asset);
+        assert_eq!(Loader::for_import("src/main.js", &[]), Loader::Javascript);
+    }
+
+    #[test]
+    fn loads_json_as_default_export() {
+        let module = load_json(String::from("\u{FEFF}{ \"name\": \"rawsemble\", \"tags\": [1, 2] }\n")).unwrap();
+        assert_eq!(module.raw_source, "export default JSON.parse(\"{ \\\"name\\\": \\\"rawsemble\\\", \\\"tags\\\": [1, 2] }\");\n");
+        assert_eq!(module.exported_names(), vec!["default"]);
+        assert!(module.imports.is_empty());
+    }
+
+    #[test]
+    fn reports_invalid_json() {
+        let err = load_json(String::from("{ \"name\": 'rawsemble' }")).unwrap_err();
+        assert_eq!(err.to_string(), "invalid JSON: expected value at line 1 column 11");
+    }
+
+    #[test]
+    fn loads_css_as_constructable_stylesheet() {
+        let module = load_css(String::from(".button { content: \"\\2014\"; }\n")).unwrap();
+        assert_eq!(module.raw_source, "const sheet = new CSSStyleSheet();\nsheet.replaceSync(\".button { content: \\\"\\\\2014\\\"; }\\n\");\nexport default sheet;\n");
+        assert_eq!(module.exported_names(), vec!["default"]);
+        assert!(module.imports.is_empty());
+    }
+
+    #[test]
+    fn imports_css_import_rules() {
+        let module = load_css(String::from("@charset \"utf-8\";\n/* base styles */\n@import url(\"base.css\");\n@import './print.css' print;\n@import url(https://fonts.example.com/font.css);\nbody { color: red; }\n")).unwrap();
+        let specifiers: Vec<&str> = module.imports.iter().map(|import| import.specifier.as_str()).collect();
+        assert_eq!(specifiers, vec!["./base.css", "./print.css"]);
+        assert_eq!(module.imports[0].attributes.len(), 1);
+        assert!(module.raw_source.contains("sheet.replaceSync(rules(dependency0) + \"@media print {\" + rules(dependency1) + \"}\" + \"\\n@import url(https://fonts.example.com/font.css);\\nbody { color: red; }\\n\");\n"));
+    }
+
+    #[test]
+    fn refers_to_relative_css_urls_as_assets() {
+        let module = load_css(String::from(".a { background: url(./bg.png); }\n.b { background: url( \"img/icon.svg#x\" ), url(data:image/png;base64,AA==), url(/logo.png); }\n/* url(./old.png) */\n.c { content: \"url(./quoted.png)\"; }\n")).unwrap();
+        let specifiers: Vec<&str> = module.asset_references.iter().map(|asset_reference| asset_reference.specifier.as_str()).collect();
+        assert_eq!(specifiers, vec!["./bg.png", "./img/icon.svg"]);
+        assert!(module.raw_source.contains("sheet.replaceSync(\".a { background: \" + \"url(\" + JSON.stringify(new URL(\"./bg.png\", import.meta.url).href) + \")\" + \"; }\\n.b { background: \" + \"url(\" + JSON.stringify(new URL(\"./img/icon.svg\", import.meta.url).href + \"#x\") + \")\" + \", url(data:image/png;base64,AA==), url(/logo.png); }"));
+    }
+
+    #[test]
+    fn escapes_media_queries_of_css_imports() {
+        let module = load_css(String::from("@import \"b.css\" screen and\n (min-width: 1px);\n")).unwrap();
+        assert!(module.raw_source.contains("sheet.replaceSync(\"@media screen and\\n (min-width: 1px) {\" + rules(dependency0) + \"}\""));
+    }
+
+    #[test]
+    fn loads_asset_as_reference_to_itself() {
+        let module = load_asset("src/images/logo.png");
+        assert_eq!(module.raw_source, "export default new URL(\"./logo.png\", import.meta.url).href;\n");
+        assert_eq!(module.asset_references.len(), 1);
+        assert_eq!(module.exported_names(), vec!["default"]);
+    }
+
+    #[test]
+    fn names_assets_after_their_contents() {
+        assert_eq!(asset_file_name("src/images/logo.png", b"png"), asset_file_name("src/logo.png", b"png"));
+        assert_ne!(asset_file_name("src/logo.png", b"png"), asset_file_name("src/logo.png", b"png2"));
+        assert!(asset_file_name("src/logo.png", b"png").starts_with("logo-"));
+        assert!(asset_file_name("src/logo.png", b"png").ends_with(".png"));
+        assert_eq!(asset_file_name("src/LICENSE", b"").len(), "LICENSE-".len() + 16);
+    }
+}
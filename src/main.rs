@@ -1,43 +1,458 @@
 use std::fs;
+use std::fmt;
+use std::io;
+use std::env::current_dir;
+use std::path::{Path, PathBuf};
+use std::process;
 pub mod lexer;
 pub mod bundler;
-use std::collections::HashMap;
-use relative_path::{RelativePath, RelativePathBuf};
-use std::env::current_dir;
+pub mod loader;
+pub mod resolver;
+pub mod import_map;
+pub mod config;
+pub mod module_graph;
+use std::collections::{BTreeSet, HashSet};
+use clap::{App, AppSettings, Arg, SubCommand};
+use relative_path::RelativePath;
 
 fn main() {
-    let mut module_map: HashMap<String, lexer::JavascriptModule> = HashMap::new();
-    let entry_file = String::from("test/fixtures/src/main.js");
-    module_map = traverse_file(entry_file.clone(), module_map);
+    let matches = App::new("rawsemble")
+        .version(clap::crate_version!())
+        .about("Bundles native ES modules into a single file")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(SubCommand::with_name("build")
+            .about("Bundles an entry module and everything it imports")
+            .arg(Arg::with_name("entry")
+                .help("Path to the entry module")
+                .required(true))
+            .arg(Arg::with_name("out")
+                .short("o")
+                .long("out")
+                .value_name("FILE")
+                .help("Path to write the bundle to")
+                .default_value("bundle.js"))
+            .arg(Arg::with_name("config")
+                .short("c")
+                .long("config")
+                .value_name("FILE")
+                .help("Config file with aliases and the tsconfig.json to read paths from [default: rawsemble.json, if it exists]"))
+            .arg(Arg::with_name("import-map")
+                .long("import-map")
+                .value_name("FILE")
+                .help("Import map to resolve specifiers with, as deployed alongside the bundle"))
+            .arg(Arg::with_name("extensions")
+                .long("extensions")
+                .value_name("EXTENSIONS")
+                .use_delimiter(true)
+                .help("Extensions to try, in order, on specifiers that do not name a file [default: .js,.mjs,.json]"))
+            .arg(Arg::with_name("external")
+                .long("external")
+                .value_name("SPECIFIER")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Leaves a specifier for the browser to load, by exact name, prefix ending in / or glob"))
+            .arg(Arg::with_name("root")
+                .long("root")
+                .value_name("DIR")
+                .help("Directory module ids are relative to [default: the config file's directory, or the nearest one above the entry with a package.json]"))
+            .arg(Arg::with_name("hashed-ids")
+                .long("hashed-ids")
+                .help("Names modules by a short hash instead of their path"))
+            .arg(Arg::with_name("runtime")
+                .long("runtime")
+                .value_name("URL")
+                .validator(|runtime_url| {
+                    if resolver::is_bare_specifier(&runtime_url) {
+                        Err(format!("must be a URL or a path starting with ./, ../ or /, not '{}'", runtime_url))
+                    } else {
+                        Ok(())
+                    }
+                })
+                .help("URL the bundle imports the bloom.js runtime from [default: /bloom.js]"))
+            .arg(Arg::with_name("inline-runtime")
+                .long("inline-runtime")
+                .conflicts_with("runtime")
+                .help("Writes the bloom.js runtime into the bundle, so that it is one self-contained file")))
+        .get_matches();
+
+    if let ("build", Some(build_matches)) = matches.subcommand() {
+        let out_file = build_matches.value_of("out").unwrap();
+        let options = BuildOptions {
+            entry_file: String::from(build_matches.value_of("entry").unwrap()),
+            out_file,
+            config_file: build_matches.value_of("config").or_else(|| Some("rawsemble.json").filter(|file| Path::new(file).is_file())),
+            import_map_file: build_matches.value_of("import-map"),
+            extensions: build_matches.values_of("extensions").map(|values| values.map(String::from).collect()),
+            externals: build_matches.values_of("external").map_or_else(Vec::new, |values| values.map(String::from).collect()),
+            root: build_matches.value_of("root"),
+            hashed_ids: build_matches.is_present("hashed-ids"),
+            runtime: build_matches.value_of("runtime"),
+            inline_runtime: build_matches.is_present("inline-runtime"),
+        };
+
+        match build(options) {
+            Ok(stats) => {
+                println!("{} written", out_file);
+                println!("  {} modules, {} assets", stats.modules, stats.assets);
+                if !stats.externals.is_empty() {
+                    let externals: Vec<&str> = stats.externals.iter().map(String::as_str).collect();
+                    println!("  externals: {}", externals.join(", "));
+                }
+            },
+            Err(errors) => {
+                for err in errors.iter() {
+                    eprintln!("error: {}", err);
+                }
+                process::exit(1);
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+enum BuildError {
+    Read { path: String, source: io::Error },
+    Write { path: String, source: io::Error },
+    Lex(lexer::LexError),
+    Load { path: String, source: loader::LoadError },
+    Resolve(resolver::ResolveError),
+    ImportMap { path: String, source: import_map::ImportMapError },
+    Config { path: String, source: config::ConfigError },
+    ModuleGraph(module_graph::ModuleGraphError),
+    Bundle(bundler::BundleError),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildError::Read { path, source } => write!(f, "unable to read {}: {}", path, source),
+            BuildError::Write { path, source } => write!(f, "unable to write {}: {}", path, source),
+            BuildError::Lex(err) => write!(f, "{}", err),
+            BuildError::Load { path, source } => write!(f, "{}: {}", path, source),
+            BuildError::Resolve(err) => write!(f, "{}", err),
+            BuildError::ImportMap { path, source } => write!(f, "{}: {}", path, source),
+            BuildError::Config { path, source } => write!(f, "{}: {}", path, source),
+            BuildError::ModuleGraph(err) => write!(f, "{}", err),
+            BuildError::Bundle(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+struct BuildOptions<'a> {
+    entry_file: String,
+    out_file: &'a str,
+    config_file: Option<&'a str>,
+    import_map_file: Option<&'a str>,
+    extensions: Option<Vec<String>>,
+    externals: Vec<String>,
+    root: Option<&'a str>,
+    hashed_ids: bool,
+    runtime: Option<&'a str>,
+    inline_runtime: bool,
+}
+
+struct BuildStats {
+    modules: usize,
+    assets: usize,
+    // every specifier that was left for the browser to load
+    externals: BTreeSet<String>,
+}
+
+struct Traversal {
+    resolver: resolver::Resolver,
+    module_graph: module_graph::ModuleGraph,
+    visited: HashSet<String>,
+    errors: Vec<BuildError>,
+}
+
+// Every file that fails to read or lex is reported, not just the first one
+fn build(options: BuildOptions) -> Result<BuildStats, Vec<BuildError>> {
+    let BuildOptions { entry_file, out_file, config_file, import_map_file, extensions, externals, root, hashed_ids, runtime, inline_runtime } = options;
+    // absolute paths are made relative to the working directory, like every other file path
+    // the build reads and resolves against
+    let entry_file = module_graph::working_path(&entry_file);
+    let config_file = config_file.map(module_graph::working_path);
+    let import_map_file = import_map_file.map(module_graph::working_path);
+    let config = match config_file.as_deref() {
+        Some(config_file) => {
+            let source = fs::read_to_string(config_file)
+                .map_err(|source| vec![BuildError::Read { path: String::from(config_file), source }])?;
+            config::Config::parse(&source, config_file)
+                .map_err(|source| vec![BuildError::Config { path: String::from(config_file), source }])?
+        },
+        None => config::Config::default(),
+    };
+
+    let root = project_root(&entry_file, root, config.root.as_deref(), config_file.as_deref());
+    // the command line wins over the config
+    let runtime = if inline_runtime {
+        bundler::Runtime::Inline
+    } else if let Some(runtime_url) = runtime {
+        bundler::Runtime::Import(String::from(runtime_url))
+    } else if config.inline_runtime {
+        bundler::Runtime::Inline
+    } else {
+        config.runtime.map_or_else(bundler::Runtime::default, bundler::Runtime::Import)
+    };
+    let module_ids = if hashed_ids || config.hashed_ids { module_graph::ModuleIds::Hashed } else { module_graph::ModuleIds::Relative };
+
+    let mut resolver = resolver::Resolver::new()
+        .with_externals(externals)
+        .with_aliases(config.aliases)
+        .with_root(module_graph::working_path(&root.to_string_lossy()));
+    if let Some(tsconfig_file) = config.tsconfig {
+        let source = fs::read_to_string(&tsconfig_file)
+            .map_err(|source| vec![BuildError::Read { path: tsconfig_file.clone(), source }])?;
+        let tsconfig_paths = config::parse_tsconfig_paths(&source, &tsconfig_file)
+            .map_err(|source| vec![BuildError::Config { path: tsconfig_file.clone(), source }])?;
+        resolver = resolver.with_tsconfig_paths(tsconfig_paths);
+    }
+    if let Some(extensions) = extensions {
+        resolver = resolver.with_extensions(extensions);
+    }
+    if let Some(import_map_file) = import_map_file.as_deref() {
+        let source = fs::read_to_string(import_map_file)
+            .map_err(|source| vec![BuildError::Read { path: String::from(import_map_file), source }])?;
+        let import_map = import_map::ImportMap::parse(&source, import_map_file)
+            .map_err(|source| vec![BuildError::ImportMap { path: String::from(import_map_file), source }])?;
+        resolver = resolver.with_import_map(import_map);
+    }
+
+    let mut traversal = Traversal {
+        resolver,
+        module_graph: module_graph::ModuleGraph::new(&entry_file, &root, module_ids),
+        visited: HashSet::new(),
+        errors: Vec::new(),
+    };
+    let entry_loader = loader::Loader::for_import(&entry_file, &[]);
+    traverse_file(entry_file, entry_loader, &mut traversal);
+
+    if !traversal.errors.is_empty() {
+        return Err(traversal.errors);
+    }
+
+    let module_graph = traversal.module_graph;
+    let stats = BuildStats {
+        // assets are in the module graph too, as modules exporting their URL
+        modules: module_graph.modules.len() - module_graph.asset_urls.len(),
+        assets: module_graph.asset_urls.len(),
+        externals: module_graph.externals(),
+    };
+
+    let bundle: bundler::JavascriptBundle = bundler::bundle(&module_graph, &runtime).map_err(|err| vec![BuildError::Bundle(err)])?;
+
+    let out_dir = Path::new(out_file).parent().unwrap_or_else(|| Path::new(""));
+    fs::create_dir_all(out_dir)
+        .and_then(|_| fs::write(out_file, bundle.content))
+        .map_err(|source| vec![BuildError::Write { path: String::from(out_file), source }])?;
 
-    let bundle: bundler::JavascriptBundle = bundler::bundle(entry_file, module_map);
+    let errors: Vec<BuildError> = module_graph.asset_urls.iter().filter_map(|(asset_id, asset_url)| {
+        let asset_path = out_dir.join(asset_url);
+        fs::create_dir_all(asset_path.parent().unwrap())
+            .and_then(|_| fs::copy(module_graph.file_path(asset_id), &asset_path))
+            .err()
+            .map(|source| BuildError::Write { path: asset_path.to_string_lossy().to_string(), source })
+    }).collect();
 
-    fs::write("test/fixtures/bundle.js", bundle.content).expect("Unable to write bundle.js");
-    println!("bundle.js written");
+    if errors.is_empty() { Ok(stats) } else { Err(errors) }
 }
 
-fn traverse_file(file_path: String, mut module_map: HashMap<String, lexer::JavascriptModule>) -> HashMap<String, lexer::JavascriptModule> {
-    let source = fs::read_to_string(file_path.clone()).expect(format!("Unable to read {}", file_path.clone()).as_str());
-    let module: lexer::JavascriptModule = lexer::JavascriptLexer::new(source).parse_module();
+// The directory module ids are relative to, which is the first of --root, the config's
+// "root", the config file's directory, the nearest directory above the entry with a
+// package.json, or else the entry's own directory
+fn project_root(entry_file: &str, root: Option<&str>, config_root: Option<&str>, config_file: Option<&str>) -> PathBuf {
+    if let Some(root) = root.or(config_root) {
+        return PathBuf::from(root);
+    }
+    if let Some(config_file) = config_file {
+        return Path::new(config_file).parent().map_or_else(PathBuf::new, Path::to_path_buf);
+    }
+    let entry_path = RelativePath::new(entry_file).to_logical_path(current_dir().unwrap());
+    let entry_directory = entry_path.parent().unwrap();
+    entry_directory.ancestors()
+        .find(|directory| directory.join("package.json").is_file())
+        .unwrap_or(entry_directory)
+        .to_path_buf()
+}
 
-    for import in module.imports.iter() {
-        let mut parent_path_buf = RelativePathBuf::from(file_path.as_str());
-        // remove filename + extension
-        parent_path_buf.pop();
-        let mod_path = parent_path_buf.join_normalized(RelativePath::new(&import.specifier));
-        module_map = traverse_file(mod_path.to_string(), module_map);
+// file_path is relative to the working directory, and is what gets read and resolved
+// against. Everything stored in the module graph is by module id.
+fn traverse_file(file_path: String, file_loader: loader::Loader, traversal: &mut Traversal) {
+    // Modules are marked as visited before their dependencies are walked, so a
+    // circular import finds its importer already visited instead of recursing forever
+    let module_id = traversal.module_graph.module_id(&file_path);
+    if !traversal.visited.insert(module_id.clone()) {
+        // a visited id reached from another file can only be a hash collision
+        if let Err(err) = traversal.module_graph.check_module_id(&module_id, &file_path) {
+            traversal.errors.push(BuildError::ModuleGraph(err));
+        }
+        return;
     }
 
-    for export in module.exports.iter() {
-        let mut parent_path_buf = RelativePathBuf::from(file_path.as_str());
-        parent_path_buf.pop();
-        let mod_path = parent_path_buf.join_normalized(RelativePath::new(&export.specifier));
-        module_map = traverse_file(mod_path.to_string(), module_map);
+    let loaded = match file_loader {
+        loader::Loader::Asset => return traverse_asset(file_path, module_id, traversal),
+        loader::Loader::Javascript => read_source(&file_path)
+            .and_then(|source| lexer::JavascriptLexer::new(source).with_file_path(file_path.clone()).parse_module().map_err(BuildError::Lex)),
+        loader::Loader::Json => read_source(&file_path)
+            .and_then(|source| loader::load_json(source).map_err(|source| BuildError::Load { path: file_path.clone(), source })),
+        loader::Loader::Css => read_source(&file_path)
+            .and_then(|source| loader::load_css(source).map_err(|source| BuildError::Load { path: file_path.clone(), source })),
+    };
+    let mut module: lexer::JavascriptModule = match loaded {
+        Ok(module) => module,
+        Err(err) => {
+            traversal.errors.push(err);
+            return;
+        }
+    };
+
+    // the file paths of the dependencies, with the loader for each
+    let mut dependencies: Vec<(String, loader::Loader)> = Vec::new();
+
+    // Every specifier is resolved here, once, and the bundler only reads the ids left behind
+    for import in module.imports.iter_mut() {
+        import.module_id = resolve_dependency(&file_path, &import.specifier, &import.attributes, traversal, &mut dependencies);
+    }
+    for export in module.exports.iter_mut() {
+        export.module_id = resolve_dependency(&file_path, &export.specifier, &export.attributes, traversal, &mut dependencies);
+    }
+    for dynamic_import in module.dynamic_imports.iter_mut() {
+        dynamic_import.module_id = resolve_dependency(&file_path, &dynamic_import.specifier, &dynamic_import.attributes, traversal, &mut dependencies);
+    }
+    // whatever `new URL()` points at is emitted as it is. It is a URL rather than a
+    // specifier, so it is always relative to the module.
+    for asset_reference in module.asset_references.iter_mut() {
+        let asset_path = resolver::join(&file_path, &asset_reference.specifier);
+        asset_reference.module_id = Some(traversal.module_graph.module_id(&asset_path));
+        dependencies.push((asset_path, loader::Loader::Asset));
     }
 
-    let full_path = RelativePath::new(file_path.as_str()).to_path(current_dir().unwrap().as_path()).to_str().unwrap().to_string();
+    if let Err(err) = traversal.module_graph.insert(&file_path, module) {
+        traversal.errors.push(BuildError::ModuleGraph(err));
+        return;
+    }
 
-    module_map.insert(full_path, module);
+    for (dependency, dependency_loader) in dependencies {
+        traverse_file(dependency, dependency_loader, traversal);
+    }
+}
 
-    module_map
+fn read_source(file_path: &str) -> Result<String, BuildError> {
+    fs::read_to_string(file_path).map_err(|source| BuildError::Read { path: String::from(file_path), source })
+}
+
+// An asset is read as bytes, to name its copy after its contents, and becomes a module
+// exporting the copy's URL
+fn traverse_asset(file_path: String, module_id: String, traversal: &mut Traversal) {
+    match fs::read(&file_path) {
+        Ok(contents) => {
+            let asset_url = format!("assets/{}", loader::asset_file_name(&file_path, &contents));
+            traversal.module_graph.asset_urls.insert(module_id.clone(), asset_url);
+        },
+        Err(source) => {
+            traversal.errors.push(BuildError::Read { path: file_path, source });
+            return;
+        }
+    }
+    let mut module = loader::load_asset(&file_path);
+    // the asset module refers to the asset itself
+    for asset_reference in module.asset_references.iter_mut() {
+        asset_reference.module_id = Some(module_id.clone());
+    }
+    if let Err(err) = traversal.module_graph.insert(&file_path, module) {
+        traversal.errors.push(BuildError::ModuleGraph(err));
+    }
+}
+
+// The id of the module a specifier resolves to, which is added to the dependencies to
+// walk, or None for an external, which the browser loads itself
+fn resolve_dependency(file_path: &str, specifier: &str, attributes: &[lexer::ImportAttribute], traversal: &mut Traversal, dependencies: &mut Vec<(String, loader::Loader)>) -> Option<String> {
+    match traversal.resolver.resolve(file_path, specifier) {
+        Ok(resolver::Resolution::File(dependency)) => {
+            let module_id = traversal.module_graph.module_id(&dependency);
+            let dependency_loader = loader::Loader::for_import(&dependency, attributes);
+            dependencies.push((dependency, dependency_loader));
+            Some(module_id)
+        },
+        Ok(resolver::Resolution::External(aliased)) => {
+            if let Some(aliased) = aliased {
+                traversal.module_graph.aliased_externals.insert(String::from(specifier), aliased);
+            }
+            None
+        },
+        Err(err) => {
+            traversal.errors.push(BuildError::Resolve(err));
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A project in its own directory under the system's temp directory, which is outside
+    // of the working directory the tests are run from
+    fn write_project(name: &str, files: Vec<(&str, &str)>) -> PathBuf {
+        let project_dir = std::env::temp_dir().join(format!("rawsemble-{}-{}", name, process::id()));
+        for (file_path, source) in files {
+            let file_path = project_dir.join(file_path);
+            fs::create_dir_all(file_path.parent().unwrap()).unwrap();
+            fs::write(file_path, source).unwrap();
+        }
+        project_dir
+    }
+
+    fn build_options(entry_file: String, out_file: &str) -> BuildOptions<'_> {
+        BuildOptions {
+            entry_file,
+            out_file,
+            config_file: None,
+            import_map_file: None,
+            extensions: None,
+            externals: Vec::new(),
+            root: None,
+            hashed_ids: false,
+            runtime: None,
+            inline_runtime: false,
+        }
+    }
+
+    #[test]
+    fn builds_absolute_entry_paths() {
+        let project_dir = write_project("absolute", vec![
+            ("package.json", "{}"),
+            ("src/main.js", "import { a } from './a.js';\nimport { b } from 'b';\n"),
+            ("src/a.js", "export const a = 'a';\n"),
+            ("lib/b.js", "export const b = 'b';\n"),
+            ("importmap.json", "{ \"imports\": { \"b\": \"./lib/b.js\" } }"),
+        ]);
+        let out_file = project_dir.join("dist/bundle.js");
+        let mut options = build_options(project_dir.join("src/main.js").to_string_lossy().to_string(), out_file.to_str().unwrap());
+        let import_map_file = project_dir.join("importmap.json");
+        options.import_map_file = import_map_file.to_str();
+        let stats = build(options).map_err(|errors| errors.iter().map(ToString::to_string).collect::<Vec<String>>()).unwrap();
+        assert_eq!(stats.modules, 3);
+        let bundle = fs::read_to_string(out_file).unwrap();
+        assert!(bundle.contains("insertModule(\"src/a.js\""));
+        assert!(bundle.contains("insertModule(\"lib/b.js\""));
+        fs::remove_dir_all(project_dir).unwrap();
+    }
+
+    #[test]
+    fn reports_every_file_that_fails_to_lex() {
+        let project_dir = write_project("unlexable", vec![
+            ("src/main.js", "import { a } from './a.js';\nimport { b } from './b.js';\n"),
+            ("src/a.js", "import { a } './x.js';\n"),
+            ("src/b.js", "export { b\n"),
+        ]);
+        let out_file = project_dir.join("dist/bundle.js");
+        let errors = build(build_options(project_dir.join("src/main.js").to_string_lossy().to_string(), out_file.to_str().unwrap())).err().unwrap();
+        assert_eq!(errors.len(), 2);
+        assert!(errors.iter().all(|err| matches!(err, BuildError::Lex(_))));
+        assert!(errors[0].to_string().contains("src/a.js:1:14: expected keyword 'from'"));
+        assert!(errors[1].to_string().contains("src/b.js:2:1: unexpected end of input"));
+        assert!(!out_file.exists());
+        fs::remove_dir_all(project_dir).unwrap();
+    }
 }
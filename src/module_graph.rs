@@ -0,0 +1,211 @@
+use super::lexer;
+use super::loader;
+use relative_path::RelativePathBuf;
+use std::collections::{BTreeSet, HashMap};
+use std::env::current_dir;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+// Every module reachable from the entry, keyed by module id. Each specifier in a module
+// carries the id of the module it resolved to, so nothing is resolved a second time.
+pub struct ModuleGraph {
+  pub entry: String,
+  pub modules: HashMap<String, lexer::JavascriptModule>,
+  // asset ids, with the paths of their hashed copies relative to the bundle
+  pub asset_urls: HashMap<String, String>,
+  // the file of each module, relative to the working directory
+  pub file_paths: HashMap<String, String>,
+  // external specifiers an alias replaced, with the specifiers the browser is given instead
+  pub aliased_externals: HashMap<String, String>,
+  root: PathBuf,
+  module_ids: ModuleIds,
+}
+
+// How modules are named in the bundle's registry
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ModuleIds {
+  // `src/main.js`, relative to the project root
+  Relative,
+  // `1a2b3c4d5e6f7a8b`, which says nothing about how the project is laid out
+  Hashed,
+}
+
+#[derive(Debug)]
+pub enum ModuleGraphError {
+  // two files were given the same hashed id
+  DuplicateModuleId { module_id: String, file_path: String, other_file_path: String },
+}
+
+impl fmt::Display for ModuleGraphError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ModuleGraphError::DuplicateModuleId { module_id, file_path, other_file_path } => {
+        write!(f, "{} and {} have the same module id {}", other_file_path, file_path, module_id)
+      },
+    }
+  }
+}
+
+impl ModuleGraph {
+  // root is the project root, either absolute or relative to the working directory
+  pub fn new(entry_path: &str, root: &Path, module_ids: ModuleIds) -> ModuleGraph {
+    let cwd = current_dir().unwrap();
+    let mut module_graph = ModuleGraph {
+      entry: String::new(),
+      modules: HashMap::new(),
+      asset_urls: HashMap::new(),
+      file_paths: HashMap::new(),
+      aliased_externals: HashMap::new(),
+      root: if root.is_absolute() { logical_path(root) } else { logical_path(&cwd.join(root)) },
+      module_ids,
+    };
+    module_graph.entry = module_graph.module_id(entry_path);
+    module_graph
+  }
+
+  // A module's id only depends on where its file is in the project, so the same file
+  // reached through `./a.js` and `lib/../a.js` is one module, and the bundle is the same
+  // whichever directory it is built from
+  pub fn module_id(&self, file_path: &str) -> String {
+    let file_path = logical_path(&current_dir().unwrap().join(file_path));
+    let module_id = relative_path(&self.root, &file_path);
+    match self.module_ids {
+      ModuleIds::Relative => module_id,
+      ModuleIds::Hashed => format!("{:016x}", loader::hash(module_id.as_bytes())),
+    }
+  }
+
+  // The file a module was loaded from, or the id itself for a module that was never inserted
+  pub fn file_path<'a>(&'a self, module_id: &'a str) -> &'a str {
+    self.file_paths.get(module_id).map_or(module_id, String::as_str)
+  }
+
+  // A module whose id already belongs to another file is rejected rather than replacing it
+  pub fn insert(&mut self, file_path: &str, module: lexer::JavascriptModule) -> Result<(), ModuleGraphError> {
+    let module_id = self.module_id(file_path);
+    self.check_module_id(&module_id, file_path)?;
+    self.file_paths.insert(module_id.clone(), String::from(file_path));
+    self.modules.insert(module_id, module);
+    Ok(())
+  }
+
+  pub fn check_module_id(&self, module_id: &str, file_path: &str) -> Result<(), ModuleGraphError> {
+    match self.file_paths.get(module_id) {
+      Some(other_file_path) if other_file_path != file_path => Err(ModuleGraphError::DuplicateModuleId {
+        module_id: String::from(module_id),
+        file_path: String::from(file_path),
+        other_file_path: other_file_path.clone(),
+      }),
+      _ => Ok(()),
+    }
+  }
+
+  // Every specifier that was left for the browser to load, as the bundle imports it
+  pub fn externals(&self) -> BTreeSet<String> {
+    let mut externals = BTreeSet::new();
+    for module in self.modules.values() {
+      let specifiers = module.imports.iter().map(|import| (&import.specifier, &import.module_id))
+        .chain(module.exports.iter().map(|export| (&export.specifier, &export.module_id)))
+        .chain(module.dynamic_imports.iter().map(|dynamic_import| (&dynamic_import.specifier, &dynamic_import.module_id)));
+      externals.extend(specifiers.filter(|(_, module_id)| module_id.is_none()).map(|(specifier, _)| {
+        self.aliased_externals.get(specifier).unwrap_or(specifier).clone()
+      }));
+    }
+    externals
+  }
+}
+
+// A path given on the command line as the rest of the build sees it, relative to the working
+// directory with `.` and `..` resolved, so `/home/me/app/src/main.js` becomes `src/main.js`
+// when the build is run from `/home/me/app`
+pub fn working_path(path: &str) -> String {
+  let cwd = current_dir().unwrap();
+  relative_path(&cwd, &logical_path(&cwd.join(path)))
+}
+
+// Resolves `.` and `..` without touching the file system
+fn logical_path(path: &Path) -> PathBuf {
+  let mut logical_path = PathBuf::new();
+  for component in path.components() {
+    match component {
+      Component::CurDir => (),
+      Component::ParentDir => {
+        logical_path.pop();
+      },
+      component => logical_path.push(component),
+    }
+  }
+  logical_path
+}
+
+// `../node_modules/lit/index.js` for a file outside of the root
+fn relative_path(root: &Path, file_path: &Path) -> String {
+  let root: Vec<Component> = root.components().collect();
+  let file_path: Vec<Component> = file_path.components().collect();
+  let common = root.iter().zip(file_path.iter()).take_while(|(a, b)| a == b).count();
+
+  let mut relative_path = RelativePathBuf::new();
+  for _ in common..root.len() {
+    relative_path.push("..");
+  }
+  for component in file_path[common..].iter() {
+    relative_path.push(component.as_os_str().to_string_lossy().as_ref());
+  }
+  relative_path.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_modules_relative_to_root() {
+        let module_graph = ModuleGraph::new("./web/src/main.js", Path::new("web"), ModuleIds::Relative);
+        assert_eq!(module_graph.entry, "src/main.js");
+        assert_eq!(module_graph.module_id("web/src/lib/../a.js"), "src/a.js");
+        assert_eq!(module_graph.module_id("node_modules/lit/index.js"), "../node_modules/lit/index.js");
+
+        let absolute_root = current_dir().unwrap().join("web");
+        assert_eq!(ModuleGraph::new("web/src/main.js", &absolute_root, ModuleIds::Relative).entry, "src/main.js");
+    }
+
+    #[test]
+    fn makes_paths_relative_to_the_working_directory() {
+        let cwd = current_dir().unwrap();
+        assert_eq!(working_path(&cwd.join("web/src/main.js").to_string_lossy()), "web/src/main.js");
+        assert_eq!(working_path("./web/lib/../src/main.js"), "web/src/main.js");
+        assert_eq!(working_path(&cwd.parent().unwrap().join("app/main.js").to_string_lossy()), "../app/main.js");
+        let module_graph = ModuleGraph::new("web/src/main.js", Path::new("web"), ModuleIds::Relative);
+        assert_eq!(module_graph.module_id(&cwd.join("web/src/a.js").to_string_lossy()), "src/a.js");
+    }
+
+    #[test]
+    fn hashes_module_ids() {
+        let module_graph = ModuleGraph::new("web/src/main.js", Path::new("web"), ModuleIds::Hashed);
+        assert_eq!(module_graph.entry.len(), 16);
+        assert_eq!(module_graph.entry, ModuleGraph::new("src/main.js", Path::new(""), ModuleIds::Hashed).entry);
+        assert_ne!(module_graph.entry, module_graph.module_id("web/src/a.js"));
+    }
+
+    #[test]
+    fn rejects_files_with_the_same_module_id() {
+        let mut module_graph = ModuleGraph::new("src/main.js", Path::new(""), ModuleIds::Relative);
+        let module = || lexer::JavascriptLexer::new(String::from("export default 1;\n")).parse_module().unwrap();
+        module_graph.insert("src/main.js", module()).unwrap();
+        module_graph.insert("src/main.js", module()).unwrap();
+        // only a hash collision could do this, so the file paths are swapped by hand
+        module_graph.file_paths.insert(String::from("src/a.js"), String::from("src/b.js"));
+        let err = module_graph.insert("src/a.js", module()).unwrap_err();
+        assert_eq!(err.to_string(), "src/b.js and src/a.js have the same module id src/a.js");
+    }
+
+    #[test]
+    fn lists_unresolved_specifiers_as_externals() {
+        let mut module = lexer::JavascriptLexer::new(String::from("import { html } from 'lit';\nexport * from './a.js';\nimport('https://cdn.example.com/x.js');\n")).parse_module().unwrap();
+        module.exports[0].module_id = Some(String::from("src/a.js"));
+        let mut module_graph = ModuleGraph::new("src/main.js", Path::new(""), ModuleIds::Relative);
+        module_graph.insert("src/main.js", module).unwrap();
+        module_graph.aliased_externals.insert(String::from("lit"), String::from("https://esm.sh/lit@3"));
+        assert_eq!(module_graph.externals().into_iter().collect::<Vec<String>>(), vec!["https://cdn.example.com/x.js", "https://esm.sh/lit@3"]);
+    }
+}
@@ -0,0 +1,495 @@
+use super::import_map::{ImportMap, ImportMapTarget};
+use relative_path::{RelativePath, RelativePathBuf};
+use std::env::current_dir;
+use std::fmt;
+use std::fs;
+use std::io;
+
+// The `exports` conditions a browser bundle matches, checked in the order the package lists them
+const EXPORT_CONDITIONS: [&str; 3] = ["import", "browser", "default"];
+
+// Tried in turn on specifiers like `./utils` that do not name a file
+const DEFAULT_EXTENSIONS: [&str; 3] = [".js", ".mjs", ".json"];
+
+#[derive(Debug)]
+pub enum ResolveError {
+  PackageNotFound { importer: String, specifier: String },
+  ReadPackageJson { path: String, source: io::Error },
+  InvalidPackageJson { path: String, source: serde_json::Error },
+  NotExported { importer: String, specifier: String, package_json: String },
+  FileNotFound { importer: String, specifier: String, candidates: Vec<String> },
+}
+
+impl fmt::Display for ResolveError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ResolveError::PackageNotFound { importer, specifier } => {
+        write!(f, "{}: cannot find package '{}' in any node_modules directory", importer, package_name(specifier))
+      },
+      ResolveError::ReadPackageJson { path, source } => write!(f, "unable to read {}: {}", path, source),
+      ResolveError::InvalidPackageJson { path, source } => write!(f, "{}: invalid JSON: {}", path, source),
+      ResolveError::NotExported { importer, specifier, package_json } => {
+        write!(f, "{}: '{}' is not exported by {}", importer, specifier, package_json)
+      },
+      ResolveError::FileNotFound { importer, specifier, candidates } => {
+        write!(f, "{}: cannot find '{}', tried {}", importer, specifier, candidates.join(", "))
+      },
+    }
+  }
+}
+
+// What a specifier was resolved to
+#[derive(Debug, Clone, PartialEq)]
+pub enum Resolution {
+  // a file that is loaded into the bundle
+  File(String),
+  // a module the browser loads on its own, by the specifier an alias replaced the original
+  // one with, or else by the original specifier
+  External(Option<String>),
+}
+
+// What an alias key is replaced with
+#[derive(Debug, Clone, PartialEq)]
+pub enum AliasTarget {
+  // relative to the working directory
+  Path(String),
+  // resolved as if it had been imported instead, like `preact/compat` for `react`
+  Specifier(String),
+}
+
+// `compilerOptions.paths` and `baseUrl` from a tsconfig.json, relative to the working directory
+#[derive(Debug, Default, PartialEq)]
+pub struct TsconfigPaths {
+  pub base_url: Option<String>,
+  // patterns with at most one `*`, each with the paths tried for it in order
+  pub paths: Vec<(String, Vec<String>)>,
+}
+
+impl TsconfigPaths {
+  // An exact pattern beats any with a `*`, and of those the longest prefix wins. Like tsc,
+  // the specifier is looked for in baseUrl as well.
+  fn candidates(&self, specifier: &str) -> Vec<String> {
+    let mut candidates: Vec<String> = match self.paths.iter().find(|(pattern, _)| pattern == specifier) {
+      Some((_, targets)) => targets.clone(),
+      None => self.paths.iter()
+        .filter_map(|(pattern, targets)| {
+          let (prefix, suffix) = pattern.split_once('*')?;
+          let matched = specifier.strip_prefix(prefix)?.strip_suffix(suffix)?;
+          Some((prefix.len(), targets, matched))
+        })
+        .max_by_key(|(prefix_length, _, _)| *prefix_length)
+        .map_or_else(Vec::new, |(_, targets, matched)| targets.iter().map(|target| target.replace('*', matched)).collect()),
+    };
+    if let Some(base_url) = &self.base_url {
+      candidates.push(RelativePath::new(base_url).join_normalized(RelativePath::new(specifier)).to_string());
+    }
+    candidates
+  }
+}
+
+pub struct Resolver {
+  externals: Vec<String>,
+  aliases: Vec<(String, AliasTarget)>,
+  tsconfig_paths: TsconfigPaths,
+  import_map: Option<ImportMap>,
+  extensions: Vec<String>,
+  // the directory `/` stands for in specifiers, relative to the working directory
+  root: String,
+}
+
+impl Default for Resolver {
+  fn default() -> Resolver {
+    Resolver {
+      externals: Vec::new(),
+      aliases: Vec::new(),
+      tsconfig_paths: TsconfigPaths::default(),
+      import_map: None,
+      extensions: DEFAULT_EXTENSIONS.iter().map(|extension| String::from(*extension)).collect(),
+      root: String::new(),
+    }
+  }
+}
+
+impl Resolver {
+  pub fn new() -> Resolver {
+    Resolver::default()
+  }
+
+  // `react` is external by that name only, `@app/` by prefix and `/vendor/*.js` by glob
+  pub fn with_externals(mut self, externals: Vec<String>) -> Resolver {
+    self.externals = externals;
+    self
+  }
+
+  // `~` replaces `~` and `~/utils.js`, but not `~utils.js`. The longest key that matches wins.
+  pub fn with_aliases(mut self, mut aliases: Vec<(String, AliasTarget)>) -> Resolver {
+    aliases.sort_by_key(|(key, _)| std::cmp::Reverse(key.len()));
+    self.aliases = aliases;
+    self
+  }
+
+  pub fn with_tsconfig_paths(mut self, tsconfig_paths: TsconfigPaths) -> Resolver {
+    self.tsconfig_paths = tsconfig_paths;
+    self
+  }
+
+  // `ts` and `.ts` are both accepted
+  pub fn with_extensions(mut self, extensions: Vec<String>) -> Resolver {
+    self.extensions = extensions.into_iter()
+      .map(|extension| if extension.starts_with('.') { extension } else { format!(".{}", extension) })
+      .collect();
+    self
+  }
+
+  pub fn with_import_map(mut self, import_map: ImportMap) -> Resolver {
+    self.import_map = Some(import_map);
+    self
+  }
+
+  // `/vendor/x.js` is looked for in the root, unless an import map says where the page is served from
+  pub fn with_root(mut self, root: String) -> Resolver {
+    self.root = root;
+    self
+  }
+
+  // Resolves a specifier imported by the file at importer_path. Externals are checked before
+  // anything else, then bare specifiers go through aliases and tsconfig paths, then the import
+  // map is consulted, then relative specifiers are joined onto the importer's directory,
+  // root-relative ones onto the root, and bare ones are looked up the way Node does, in the
+  // nearest node_modules directory that has the package. Remote URLs are left to the browser.
+  pub fn resolve(&self, importer_path: &str, specifier: &str) -> Result<Resolution, ResolveError> {
+    if self.is_external(specifier) {
+      return Ok(Resolution::External(None));
+    }
+    if !is_bare_specifier(specifier) {
+      return self.resolve_unaliased(importer_path, specifier);
+    }
+
+    let alias = self.aliases.iter().find(|(key, _)| {
+      specifier.strip_prefix(key.as_str()).is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    });
+    if let Some((key, target)) = alias {
+      let rest = &specifier[key.len()..];
+      return match target {
+        AliasTarget::Path(path) => {
+          let file_path = RelativePath::new(path).join_normalized(RelativePath::new(rest.trim_start_matches('/'))).to_string();
+          self.find_file(importer_path, specifier, &file_path).map(Resolution::File)
+        },
+        // the replacement is not aliased again, so `react` can be aliased to `react/index.js`.
+        // When it is external, the browser is given the replacement in place of the original.
+        AliasTarget::Specifier(target) => {
+          let aliased = format!("{}{}", target, rest);
+          if self.is_external(&aliased) {
+            return Ok(Resolution::External(Some(aliased)));
+          }
+          match self.resolve_unaliased(importer_path, &aliased)? {
+            Resolution::External(None) => Ok(Resolution::External(Some(aliased))),
+            resolution => Ok(resolution),
+          }
+        },
+      };
+    }
+
+    // tsconfig paths that name no file fall through to node_modules, as they do for tsc
+    let found = self.tsconfig_paths.candidates(specifier).iter().find_map(|candidate| self.find_file(importer_path, specifier, candidate).ok());
+    match found {
+      Some(file_path) => Ok(Resolution::File(file_path)),
+      None => self.resolve_unaliased(importer_path, specifier),
+    }
+  }
+
+  fn resolve_unaliased(&self, importer_path: &str, specifier: &str) -> Result<Resolution, ResolveError> {
+    let file_path = match self.import_map.as_ref().and_then(|import_map| import_map.resolve(importer_path, specifier)) {
+      Some(ImportMapTarget::Path(file_path)) => file_path,
+      // A module in the bundle is loaded from a blob: URL, which a path cannot be resolved
+      // against, so the browser is given the URL a path is mapped to rather than the path
+      Some(ImportMapTarget::Url(url)) if !is_bare_specifier(specifier) && !is_url(specifier) => return Ok(Resolution::External(Some(url))),
+      Some(ImportMapTarget::Url(_)) => return Ok(Resolution::External(None)),
+      None if is_url(specifier) => return Ok(Resolution::External(None)),
+      None if is_bare_specifier(specifier) => resolve_package_specifier(importer_path, specifier)?,
+      // unmapped root-relative specifiers are relative to the directory the page is served from
+      None if specifier.starts_with('/') => {
+        let root = self.import_map.as_ref().map_or(self.root.as_str(), ImportMap::base_path);
+        RelativePath::new(root).join_normalized(RelativePath::new(specifier.trim_start_matches('/'))).to_string()
+      },
+      None => join(importer_path, specifier),
+    };
+
+    self.find_file(importer_path, specifier, &file_path).map(Resolution::File)
+  }
+
+  fn is_external(&self, specifier: &str) -> bool {
+    self.externals.iter().any(|external| {
+      if external.contains('*') {
+        matches_glob(external, specifier)
+      } else if external.ends_with('/') {
+        specifier.starts_with(external.as_str())
+      } else {
+        external == specifier
+      }
+    })
+  }
+
+  // The file itself, then the file with each extension, then the index file of the
+  // directory with each extension. Every candidate is reported if none of them exist.
+  fn find_file(&self, importer_path: &str, specifier: &str, file_path: &str) -> Result<String, ResolveError> {
+    let index_path = RelativePath::new(file_path).join("index").to_string();
+    let candidates: Vec<String> = std::iter::once(String::from(file_path))
+      .chain(self.extensions.iter().map(|extension| format!("{}{}", file_path, extension)))
+      .chain(self.extensions.iter().map(|extension| format!("{}{}", index_path, extension)))
+      .collect();
+
+    let cwd = current_dir().unwrap();
+    match candidates.iter().find(|candidate| RelativePath::new(candidate).to_path(&cwd).is_file()) {
+      Some(candidate) => Ok(candidate.clone()),
+      None => Err(ResolveError::FileNotFound {
+        importer: String::from(importer_path),
+        specifier: String::from(specifier),
+        candidates,
+      }),
+    }
+  }
+}
+
+// Joins a relative specifier onto the directory of the file at importer_path
+pub fn join(importer_path: &str, specifier: &str) -> String {
+  let mut directory = RelativePathBuf::from(importer_path);
+  // remove filename + extension
+  directory.pop();
+  directory.join_normalized(RelativePath::new(specifier)).to_string()
+}
+
+// `lit` or `@lit/reactive-element/decorators.js`, as opposed to `./a.js`, `/a.js` or a URL
+pub fn is_bare_specifier(specifier: &str) -> bool {
+  let is_path = specifier == "." || specifier == ".." || specifier.starts_with("./") || specifier.starts_with("../") || specifier.starts_with('/');
+  !is_path && !is_url(specifier)
+}
+
+// `https://cdn.example.com/lit.js`, or any other specifier that starts with a scheme
+pub fn is_url(specifier: &str) -> bool {
+  specifier.split('/').next().is_some_and(|scheme| scheme.contains(':'))
+}
+
+fn resolve_package_specifier(importer_path: &str, specifier: &str) -> Result<String, ResolveError> {
+  let mut directory = RelativePathBuf::from(importer_path);
+  directory.pop();
+
+  let cwd = current_dir().unwrap();
+  loop {
+    let package_path = directory.join_normalized(RelativePath::new("node_modules").join(package_name(specifier)));
+    if package_path.to_path(&cwd).is_dir() {
+      return resolve_package(importer_path, specifier, &package_path);
+    }
+    // node_modules directories above the working directory are searched too, up to the root
+    if directory.to_logical_path(&cwd).parent().is_none() {
+      return Err(ResolveError::PackageNotFound {
+        importer: String::from(importer_path),
+        specifier: String::from(specifier),
+      });
+    }
+    directory = directory.join_normalized("..");
+  }
+}
+
+// `*` matches any run of characters, `/` included
+fn matches_glob(pattern: &str, text: &str) -> bool {
+  match pattern.split_once('*') {
+    Some((prefix, pattern)) => text.strip_prefix(prefix).is_some_and(|text| {
+      text.char_indices().map(|(index, _)| index).chain(std::iter::once(text.len())).any(|index| matches_glob(pattern, &text[index..]))
+    }),
+    None => pattern == text,
+  }
+}
+
+// `@scope/name` for scoped packages and `name` for the rest
+fn package_name(specifier: &str) -> &str {
+  let name_length = if specifier.starts_with('@') { 2 } else { 1 };
+  match specifier.match_indices('/').nth(name_length - 1) {
+    Some((name_end, _)) => &specifier[..name_end],
+    None => specifier,
+  }
+}
+
+fn resolve_package(importer_path: &str, specifier: &str, package_path: &RelativePath) -> Result<String, ResolveError> {
+  let package_json_path = package_path.join("package.json");
+  let package_json = match fs::read_to_string(package_json_path.to_path(current_dir().unwrap())) {
+    Ok(source) => serde_json::from_str(&source).map_err(|source| ResolveError::InvalidPackageJson { path: package_json_path.to_string(), source })?,
+    Err(err) if err.kind() == io::ErrorKind::NotFound => serde_json::Value::Null,
+    Err(source) => return Err(ResolveError::ReadPackageJson { path: package_json_path.to_string(), source }),
+  };
+
+  // `lit/decorators.js` is the `./decorators.js` subpath of `lit`
+  let subpath = format!(".{}", &specifier[package_name(specifier).len()..]);
+
+  let target = match package_json.get("exports") {
+    // a package with `exports` cannot be imported from anywhere it does not list
+    Some(exports) => resolve_exports(exports, &subpath).ok_or_else(|| ResolveError::NotExported {
+      importer: String::from(importer_path),
+      specifier: String::from(specifier),
+      package_json: package_json_path.to_string(),
+    })?,
+    None if subpath == "." => ["module", "main"].iter()
+      .find_map(|field| package_json.get(field).and_then(|main| main.as_str()))
+      .unwrap_or("index.js")
+      .to_string(),
+    None => subpath,
+  };
+
+  Ok(package_path.join_normalized(RelativePath::new(&target)).to_string())
+}
+
+// Finds the subpath in an `exports` field, which is either a map of subpaths, possibly
+// with `*` patterns, or the target of the package's main entry on its own
+fn resolve_exports(exports: &serde_json::Value, subpath: &str) -> Option<String> {
+  let subpaths = match exports.as_object() {
+    Some(object) if object.keys().any(|key| key.starts_with('.')) => object,
+    _ if subpath == "." => return export_target(exports, ""),
+    _ => return None,
+  };
+
+  if let Some(target) = subpaths.get(subpath) {
+    return export_target(target, "");
+  }
+
+  // the pattern with the longest prefix before its `*` wins
+  subpaths.iter()
+    .filter_map(|(key, target)| {
+      let (prefix, suffix) = key.split_once('*')?;
+      let matched = subpath.strip_prefix(prefix)?.strip_suffix(suffix)?;
+      Some((prefix.len(), target, matched))
+    })
+    .max_by_key(|(prefix_length, _, _)| *prefix_length)
+    .and_then(|(_, target, matched)| export_target(target, matched))
+}
+
+// A target is a path, a list of fallbacks, or a map of conditions to targets.
+// null targets keep a subpath private.
+fn export_target(target: &serde_json::Value, pattern_match: &str) -> Option<String> {
+  match target {
+    serde_json::Value::String(path) => Some(path.replace('*', pattern_match)),
+    serde_json::Value::Array(targets) => targets.iter().find_map(|target| export_target(target, pattern_match)),
+    serde_json::Value::Object(conditions) => conditions.iter()
+      .filter(|(condition, _)| EXPORT_CONDITIONS.contains(&condition.as_str()))
+      .find_map(|(_, target)| export_target(target, pattern_match)),
+    _ => None,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_relative_specifiers_against_importer() {
+        let resolver = Resolver::new();
+        assert_eq!(resolver.resolve("test/fixtures/src/main.js", "./a.js").unwrap(), Resolution::File(String::from("test/fixtures/src/a.js")));
+        assert_eq!(resolver.resolve("test/fixtures/src/utils/index.js", "../b.js").unwrap(), Resolution::File(String::from("test/fixtures/src/b.js")));
+    }
+
+    #[test]
+    fn tries_extensions_and_index_files() {
+        let resolver = Resolver::new();
+        assert_eq!(resolver.resolve("test/fixtures/src/main.js", "./a").unwrap(), Resolution::File(String::from("test/fixtures/src/a.js")));
+        assert_eq!(resolver.resolve("test/fixtures/src/main.js", "./config").unwrap(), Resolution::File(String::from("test/fixtures/src/config.json")));
+        assert_eq!(resolver.resolve("test/fixtures/src/main.js", "./utils/").unwrap(), Resolution::File(String::from("test/fixtures/src/utils/index.js")));
+
+        let err = Resolver::new().with_extensions(vec![String::from("ts"), String::from(".js")]).resolve("test/fixtures/src/main.js", "./missing").unwrap_err();
+        assert_eq!(err.to_string(), "test/fixtures/src/main.js: cannot find './missing', tried test/fixtures/src/missing, test/fixtures/src/missing.ts, test/fixtures/src/missing.js, test/fixtures/src/missing/index.ts, test/fixtures/src/missing/index.js");
+    }
+
+    #[test]
+    fn resolves_bare_specifiers_through_package_exports() {
+        let resolve = |specifier| resolve_package_specifier("test/fixtures/src/main.js", specifier);
+        assert_eq!(resolve("conditional").unwrap(), "test/fixtures/node_modules/conditional/index.mjs");
+        assert_eq!(resolve("conditional/features/tooltip.js").unwrap(), "test/fixtures/node_modules/conditional/src/features/tooltip.js");
+        assert_eq!(resolve("@scope/sugar").unwrap(), "test/fixtures/node_modules/@scope/sugar/sugar.js");
+
+        let err = resolve("conditional/internal/state.js").unwrap_err();
+        assert_eq!(err.to_string(), "test/fixtures/src/main.js: 'conditional/internal/state.js' is not exported by test/fixtures/node_modules/conditional/package.json");
+        assert!(resolve("conditional/index.cjs").is_err());
+    }
+
+    #[test]
+    fn resolves_bare_specifiers_through_module_and_main() {
+        let resolve = |specifier| resolve_package_specifier("test/fixtures/src/main.js", specifier);
+        assert_eq!(resolve("legacy").unwrap(), "test/fixtures/node_modules/legacy/esm/index.js");
+        assert_eq!(resolve("legacy/cjs/index.js").unwrap(), "test/fixtures/node_modules/legacy/cjs/index.js");
+    }
+
+    #[test]
+    fn reports_missing_packages() {
+        let err = Resolver::new().resolve("test/fixtures/src/main.js", "@scope/missing/a.js").unwrap_err();
+        assert_eq!(err.to_string(), "test/fixtures/src/main.js: cannot find package '@scope/missing' in any node_modules directory");
+    }
+
+    #[test]
+    fn resolves_through_import_map_first() {
+        let import_map = ImportMap::parse(r#"{ "imports": { "conditional": "./src/utils/index.js", "legacy": "https://cdn.example.com/legacy.js" } }"#, "test/fixtures/importmap.json").unwrap();
+        let resolver = Resolver::new().with_import_map(import_map);
+        assert_eq!(resolver.resolve("test/fixtures/src/main.js", "conditional").unwrap(), Resolution::File(String::from("test/fixtures/src/utils/index.js")));
+        assert_eq!(resolver.resolve("test/fixtures/src/main.js", "legacy").unwrap(), Resolution::External(None));
+        assert_eq!(resolver.resolve("test/fixtures/src/main.js", "https://cdn.example.com/other.js").unwrap(), Resolution::External(None));
+        assert_eq!(resolver.resolve("test/fixtures/src/main.js", "@scope/sugar").unwrap(), Resolution::File(String::from("test/fixtures/node_modules/@scope/sugar/sugar.js")));
+    }
+
+    #[test]
+    fn resolves_root_relative_specifiers_against_root() {
+        let resolver = Resolver::new().with_root(String::from("test/fixtures"));
+        assert_eq!(resolver.resolve("test/fixtures/src/utils/index.js", "/src/a.js").unwrap(), Resolution::File(String::from("test/fixtures/src/a.js")));
+
+        // the import map's directory is where the page is served from, whatever the root
+        let import_map = ImportMap::parse(r#"{ "imports": {} }"#, "test/fixtures/src/importmap.json").unwrap();
+        let resolver = resolver.with_import_map(import_map);
+        assert_eq!(resolver.resolve("test/fixtures/src/main.js", "/b.js").unwrap(), Resolution::File(String::from("test/fixtures/src/b.js")));
+    }
+
+    #[test]
+    fn gives_the_browser_urls_that_paths_are_mapped_to() {
+        let import_map = ImportMap::parse(r#"{ "imports": { "./vendor/legacy.js": "https://cdn.example.com/legacy.js", "/vendor/": "https://cdn.example.com/vendor/" } }"#, "test/fixtures/importmap.json").unwrap();
+        let resolver = Resolver::new().with_import_map(import_map);
+        assert_eq!(resolver.resolve("test/fixtures/src/main.js", "../vendor/legacy.js").unwrap(), Resolution::External(Some(String::from("https://cdn.example.com/legacy.js"))));
+        assert_eq!(resolver.resolve("test/fixtures/src/main.js", "/vendor/lit.js").unwrap(), Resolution::External(Some(String::from("https://cdn.example.com/vendor/lit.js"))));
+    }
+
+    #[test]
+    fn resolves_externals_before_anything_else() {
+        let import_map = ImportMap::parse(r#"{ "imports": { "react": "./src/utils/index.js" } }"#, "test/fixtures/importmap.json").unwrap();
+        let externals = vec![String::from("react"), String::from("@app/"), String::from("/vendor/*.js")];
+        let resolver = Resolver::new().with_import_map(import_map).with_externals(externals);
+        assert_eq!(resolver.resolve("test/fixtures/src/main.js", "react").unwrap(), Resolution::External(None));
+        assert_eq!(resolver.resolve("test/fixtures/src/main.js", "@app/ui/button.js").unwrap(), Resolution::External(None));
+        assert_eq!(resolver.resolve("test/fixtures/src/main.js", "/vendor/charts/line.js").unwrap(), Resolution::External(None));
+        assert!(resolver.resolve("test/fixtures/src/main.js", "react-dom").is_err());
+        assert!(resolver.resolve("test/fixtures/src/main.js", "@app").is_err());
+    }
+
+    #[test]
+    fn matches_globs_across_path_segments() {
+        assert!(matches_glob("https://*.example.com/*.js", "https://cdn.example.com/lib/x.js"));
+        assert!(matches_glob("*", ""));
+        assert!(!matches_glob("https://*.example.com/*.js", "https://cdn.example.org/x.js"));
+        assert!(!matches_glob("lodash-*", "lodash"));
+    }
+
+    #[test]
+    fn resolves_aliases_and_tsconfig_paths() {
+        let aliases = vec![
+            (String::from("~"), AliasTarget::Path(String::from("test/fixtures/src"))),
+            (String::from("react"), AliasTarget::Specifier(String::from("@scope/sugar"))),
+            (String::from("preact"), AliasTarget::Specifier(String::from("https://esm.sh/preact@10"))),
+        ];
+        let tsconfig_paths = TsconfigPaths {
+            base_url: Some(String::from("test/fixtures/src")),
+            paths: vec![(String::from("@fixtures/*"), vec![String::from("test/fixtures/missing/*"), String::from("test/fixtures/src/*")])],
+        };
+        let resolver = Resolver::new().with_aliases(aliases).with_tsconfig_paths(tsconfig_paths);
+        let resolve = |specifier| resolver.resolve("test/fixtures/src/utils/index.js", specifier).unwrap();
+        assert_eq!(resolve("~/utils/"), Resolution::File(String::from("test/fixtures/src/utils/index.js")));
+        assert_eq!(resolve("react"), Resolution::File(String::from("test/fixtures/node_modules/@scope/sugar/sugar.js")));
+        assert_eq!(resolve("preact/hooks"), Resolution::External(Some(String::from("https://esm.sh/preact@10/hooks"))));
+        assert_eq!(resolve("@fixtures/a"), Resolution::File(String::from("test/fixtures/src/a.js")));
+        assert_eq!(resolve("b.js"), Resolution::File(String::from("test/fixtures/src/b.js")));
+        assert_eq!(resolve("legacy"), Resolution::File(String::from("test/fixtures/node_modules/legacy/esm/index.js")));
+        assert!(resolver.resolve("test/fixtures/src/main.js", "~utils").is_err());
+    }
+}
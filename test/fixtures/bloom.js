@@ -0,0 +1 @@
+../../src/bloom.js
\ No newline at end of file
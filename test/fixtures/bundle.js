@@ -1,34 +1,37 @@
-import { insertModule, createModuleUrl, resolveImportSpecifier } from "/bloom.js";
-insertModule("test/fixtures/src/b.js",createModuleUrl(`const b = 're-export';
+import { insertModule, insertProxyModule, createModuleUrl, resolveImportSpecifier } from "/bloom.js";
+insertModule("src/b.js",createModuleUrl(`const b = 're-export';
 
 export { b };
 `));
-insertModule("test/fixtures/src/e.js",createModuleUrl(`const e = 're-export e-aggregate';
+insertModule("src/e.js",createModuleUrl(`const e = 're-export e-aggregate';
 
 export { e };
 `));
-insertModule("test/fixtures/src/d.js",createModuleUrl(`const d = 're-export default';
+insertModule("src/d.js",createModuleUrl(`const d = 're-export default';
 
 export default d;
 `));
-insertModule("test/fixtures/src/a-default.js",createModuleUrl(`const A = () => {};
+insertModule("src/a-default.js",createModuleUrl(`const A = () => {};
 
 export { A };
 `));
-insertModule("test/fixtures/src/a.js",createModuleUrl(`export const a = 'a value';
+insertModule("src/a.js",createModuleUrl(`export const a = 'a value';
 
-export { b } from '${resolveImportSpecifier("test/fixtures/src/b.js")}';
-export * from '${resolveImportSpecifier("test/fixtures/src/e.js")}';
-export { default as DModule } from '${resolveImportSpecifier("test/fixtures/src/d.js")}';
-export { A as default } from '${resolveImportSpecifier("test/fixtures/src/a-default.js")}';
+export { b } from '${resolveImportSpecifier("src/b.js")}';
+export * from '${resolveImportSpecifier("src/e.js")}';
+export { default as DModule } from '${resolveImportSpecifier("src/d.js")}';
+export { A as default } from '${resolveImportSpecifier("src/a-default.js")}';
 `));
-insertModule("test/fixtures/src/c-default.js",createModuleUrl(`const c = 'default export';
+insertModule("src/c-default.js",createModuleUrl(`const c = 'default export';
 
 export default c;
 `));
-insertModule("test/fixtures/src/main.js",createModuleUrl(`import A, { a, b, DModule, e } from '${resolveImportSpecifier("test/fixtures/src/a.js")}';
-import c from '${resolveImportSpecifier("test/fixtures/src/c-default.js")}';
+insertModule("src/config.json",createModuleUrl(`export default JSON.parse("{\\n  \\"greeting\\": \\"hi there\\"\\n}");
+`));
+insertModule("src/main.js",createModuleUrl(`import A, { a, b, DModule, e } from '${resolveImportSpecifier("src/a.js")}';
+import c from '${resolveImportSpecifier("src/c-default.js")}';
+import config from '${resolveImportSpecifier("src/config.json")}';
 
-console.log('hi there', A, a, b, c, DModule, e);
+console.log(config.greeting, A, a, b, c, DModule, e);
 `));
-import(resolveImportSpecifier("test/fixtures/src/main.js"));
\ No newline at end of file
+import(resolveImportSpecifier("src/main.js"));
\ No newline at end of file
@@ -1,4 +1,5 @@
 import A, { a, b, DModule, e } from './a.js';
 import c from './c-default.js';
+import config from './config.json' with { type: 'json' };
 
-console.log('hi there', A, a, b, c, DModule, e);
+console.log(config.greeting, A, a, b, c, DModule, e);